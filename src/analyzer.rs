@@ -0,0 +1,980 @@
+//! Parsing of `dumpsys package dexopt` output.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use clap::ValueEnum;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::DexoptError;
+use crate::package::{AppType, Package};
+use crate::shell::{decode_lossy, Shell};
+use crate::status::Status;
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Dimension {
+    /// The compiler filter / dexopt status
+    Status,
+    /// The compilation reason (install, bg-dexopt, ...)
+    Reason,
+}
+
+/// A package paired with its resolved label and dexopt entries.
+pub type DisplayRow<'a> = (&'a Package, Option<String>, Option<&'a Vec<DexOptInfo>>);
+
+/// Scores a package by its least optimized entry ([`Status::score`]).
+/// Packages without any dexopt info rank below everything else.
+pub fn package_score(info_list: Option<&Vec<DexOptInfo>>) -> i32 {
+    info_list.and_then(|infos| infos.iter().map(|i| i.status_kind().score()).min()).unwrap_or(-2)
+}
+
+/// One compiled artifact reported for a package (one ABI of one dex file).
+#[derive(Debug, Clone, Serialize)]
+pub struct DexOptInfo {
+    pub raw_line: String,
+    /// Instruction set from the line's `<isa>:` prefix.
+    pub abi: Abi,
+    pub status: String,
+    /// The whole `status=`/`filter=` attribute as written, including any
+    /// comma-joined attributes packed after the filter name
+    /// (`speed-profile,location=...`); `status` keeps only the first token.
+    pub status_raw: String,
+    /// Why the package was compiled with its current filter (install, bg-dexopt, cmdline, ...).
+    pub reason: Option<String>,
+    /// Whether the entry belongs to a secondary dex file loaded by the app
+    /// (e.g. under `/data/user`) rather than the APK itself.
+    pub is_secondary: bool,
+    /// Compiler / OAT version the artifact was produced with, when the dump reports it.
+    pub version: Option<String>,
+    /// Set when `version` differs from the version most entries in the dump share,
+    /// which usually means the artifact predates the last OTA.
+    pub version_mismatch: bool,
+    /// When the artifact was produced: a timestamp reported in the dump, or
+    /// the mtime of the on-disk oat/vdex file.
+    pub compiled_at: Option<String>,
+    /// Whether the line reports a collected profile (`[profile-present]`,
+    /// `[has-profile]`, `[profile=present]`).
+    pub has_profile: bool,
+    /// The dex file or APK the entry belongs to: the `path:` or bare dex path
+    /// line preceding it in the dump.
+    pub path: Option<String>,
+}
+
+/// Instruction set a dexopt entry was compiled for.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Abi {
+    Arm64,
+    Arm,
+    #[serde(rename = "x86_64")]
+    X86_64,
+    X86,
+    Riscv64,
+    Mips64,
+    Mips,
+    Unknown,
+}
+
+impl Abi {
+    /// Every known ABI, 64-bit before 32-bit within each family.
+    pub const ALL: [Abi; 7] = [Abi::Arm64, Abi::Arm, Abi::X86_64, Abi::X86, Abi::Riscv64, Abi::Mips64, Abi::Mips];
+
+    /// Maps an ART instruction-set name (`arm64`, `x86_64`, ...) to an ABI.
+    pub fn from_isa(isa: &str) -> Self {
+        match isa {
+            "arm64" => Abi::Arm64,
+            "arm" => Abi::Arm,
+            "x86_64" => Abi::X86_64,
+            "x86" => Abi::X86,
+            "riscv64" => Abi::Riscv64,
+            "mips64" => Abi::Mips64,
+            "mips" => Abi::Mips,
+            _ => Abi::Unknown,
+        }
+    }
+
+    /// Detects the ABI from an `<isa>:` prefix of a dexopt line.
+    pub fn from_line(line: &str) -> Self {
+        line.split_once(':').map_or(Abi::Unknown, |(isa, _)| Self::from_isa(isa.trim()))
+    }
+
+    /// The ISA directory name ART uses under `oat/`, if known.
+    pub fn isa(&self) -> Option<&'static str> {
+        match self {
+            Abi::Arm64 => Some("arm64"),
+            Abi::Arm => Some("arm"),
+            Abi::X86_64 => Some("x86_64"),
+            Abi::X86 => Some("x86"),
+            Abi::Riscv64 => Some("riscv64"),
+            Abi::Mips64 => Some("mips64"),
+            Abi::Mips => Some("mips"),
+            Abi::Unknown => None,
+        }
+    }
+}
+
+impl fmt::Display for Abi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.isa().unwrap_or("?"))
+    }
+}
+
+impl DexOptInfo {
+    /// The value of this entry along `dim`, with missing reasons bucketed as "unknown".
+    pub fn dimension_value(&self, dim: Dimension) -> &str {
+        match dim {
+            Dimension::Status => &self.status,
+            Dimension::Reason => self.reason.as_deref().unwrap_or("unknown"),
+        }
+    }
+
+    /// A profile is available but the artifact wasn't compiled with it, so a
+    /// `speed-profile` recompile would help.
+    pub fn is_optimizable(&self) -> bool {
+        self.has_profile && matches!(self.status_kind(), Status::RunFromApk | Status::Verify)
+    }
+
+    /// The last component of `path` (`base.apk`, `split_config.arm64_v8a.apk`).
+    pub fn file_name(&self) -> Option<&str> {
+        self.path.as_deref().map(|p| p.rsplit('/').next().unwrap_or(p))
+    }
+
+    /// The parsed [`Status`]; statuses this build doesn't know are [`Status::Unknown`].
+    pub fn status_kind(&self) -> Status {
+        Status::classify(&self.status)
+    }
+
+    /// The raw line without its ABI prefix, for layouts that show the ABI separately.
+    pub fn details(&self) -> &str {
+        match self.abi {
+            Abi::Unknown => &self.raw_line,
+            _ => self.raw_line.split_once(':').map_or(&self.raw_line, |(_, rest)| rest.trim_start()),
+        }
+    }
+}
+
+/// Dexopt entries parsed from a dump, keyed by package name.
+pub struct Analyzer {
+    results: HashMap<String, Vec<DexOptInfo>>,
+    /// Every non-blank line of each package's block, header included, verbatim.
+    blocks: HashMap<String, Vec<String>>,
+}
+
+/// Compiler filters and dump states that can appear in a dexopt status field:
+/// the names of [`Status::ALL`], for APIs that take strings.
+pub const KNOWN_STATUSES: [&str; Status::ALL.len()] = {
+    let mut names = [""; Status::ALL.len()];
+    let mut i = 0;
+    while i < names.len() {
+        names[i] = Status::ALL[i].as_str();
+        i += 1;
+    }
+    names
+};
+
+/// Compiler filters accepted by `cmd package compile -m`.
+pub const COMPILER_FILTERS: &[&str] = &[
+    "assume-verified",
+    "extract",
+    "verify",
+    "quicken",
+    "space-profile",
+    "space",
+    "speed-profile",
+    "speed",
+    "everything-profile",
+    "everything",
+];
+
+/// One `--compiler-filter-recommendation` rule: entries of `app_type` (any
+/// when `None`) compiled for one of `reasons` (any when empty) and currently
+/// on one of `statuses` should be on `suggest`.
+struct Recommendation {
+    app_type: Option<AppType>,
+    reasons: &'static [&'static str],
+    statuses: &'static [&'static str],
+    suggest: &'static str,
+}
+
+/// Rules for [`recommended_filter`], checked in order; the first match wins.
+/// They mirror the defaults recent Android releases converge on.
+const RECOMMENDATIONS: &[Recommendation] = &[
+    // User apps should end up on their profile once bg-dexopt has run
+    Recommendation {
+        app_type: Some(AppType::User),
+        reasons: &["install", "install-fast", "bg-dexopt", "first-boot", "boot-after-ota", "ab-ota"],
+        statuses: &["verify", "quicken", "run-from-apk", "extract"],
+        suggest: "speed-profile",
+    },
+    // System apps get profiles from the background job as well
+    Recommendation {
+        app_type: Some(AppType::System),
+        reasons: &["bg-dexopt"],
+        statuses: &["verify", "quicken"],
+        suggest: "speed-profile",
+    },
+    // Anything interpreting straight from the APK should at least be verified
+    Recommendation {
+        app_type: None,
+        reasons: &[],
+        statuses: &["run-from-apk", "run-from-apk-fallback", "extract"],
+        suggest: "verify",
+    },
+];
+
+/// The compiler filter an entry should be on given why it was compiled and
+/// its current status, or `None` when it is already where it should be.
+pub fn recommended_filter(app_type: AppType, reason: Option<&str>, status: &str) -> Option<&'static str> {
+    let reason = reason.unwrap_or("unknown");
+    RECOMMENDATIONS
+        .iter()
+        .find(|r| {
+            r.app_type.is_none_or(|t| t == app_type)
+                && (r.reasons.is_empty() || r.reasons.contains(&reason))
+                && r.statuses.contains(&status)
+        })
+        .map(|r| r.suggest)
+}
+
+static STATUS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(arm64|arm|x86_64|x86|riscv64|mips64|mips):").expect("Invalid regex for status"));
+static FILTER_EXTRACT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(?:status|filter)=([^\]\s,]+)").expect("Invalid regex for filter extraction"));
+static STATUS_RAW_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(?:status|filter)=([^\]\s]+)").expect("Invalid regex for raw status extraction"));
+static REASON_EXTRACT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\breason=([^\]\s,]+)").expect("Invalid regex for reason extraction"));
+static COMPILED_AT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[(?:timestamp|compiled-at|compile-time|last-modified)=([^\]]+)\]").expect("Invalid regex for timestamp extraction"));
+static VERSION_EXTRACT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(?:dexopt-version|oat-version|compiler-version)=([^\]\s,]+)").expect("Invalid regex for version extraction"));
+static PROFILE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[(?:profile-present|has-profile|profile=(?:present|yes|true))\]").expect("Invalid regex for profile indicator"));
+/// `[com.example.app]`, optionally followed by bracketed metadata some ROMs
+/// append (`[com.example.app] [uid=10123]`). The name needs at least one dot
+/// (the framework's `android` is the one dotless package), so bracketed
+/// tokens like `[libraries]` inside a block aren't taken for headers.
+static PACKAGE_HEADER_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\[([A-Za-z][A-Za-z0-9_]*(?:\.[A-Za-z0-9_]+)+|android)\](?:\s*\[[^\]]*\])*$").expect("Invalid regex for package header"));
+
+/// Where [`Analyzer::new`] is in the dump: either between package blocks
+/// (preamble, section titles, trailers) or inside one.
+enum ParseState {
+    Outside,
+    Package {
+        name: String,
+        /// Indentation of the header line; the block's lines sit deeper.
+        indent: usize,
+        in_secondary: bool,
+        /// Most recent `path:` / bare dex path line in the block.
+        path: Option<String>,
+    },
+}
+
+impl Analyzer {
+    /// Runs `dumpsys package dexopt`, or reads the dump from `source` when a
+    /// saved capture is given.
+    pub fn fetch_dump(shell: &Shell, source: Option<&Path>) -> Result<String, DexoptError> {
+        if let Some(path) = source {
+            let raw = std::fs::read(path).map_err(|source| DexoptError::FileRead {
+                what: "dexopt dump",
+                path: path.to_path_buf(),
+                source,
+            })?;
+            return Ok(decode_lossy(&raw, &format!("The dexopt dump in {}", path.display())));
+        }
+
+        let mut cmd = shell.command("dumpsys", &["package", "dexopt"]);
+        let output = shell.checked_output("dumpsys", "dumpsys", &mut cmd)?;
+        Ok(decode_lossy(&output.stdout, "The dexopt dump"))
+    }
+
+    /// Parses `dumpsys package dexopt` output. Unrecognised lines are skipped,
+    /// so partial or garbled dumps yield whatever entries could be read.
+    pub fn new(dump: &str) -> Self {
+        Self::parse(dump, None)
+    }
+
+    /// Parses only the block of `package`, stopping once it has been read.
+    pub fn for_package(dump: &str, package: &str) -> Self {
+        Self::parse(dump, Some(package))
+    }
+
+    fn parse(dump: &str, only: Option<&str>) -> Self {
+        let mut results: HashMap<String, Vec<DexOptInfo>> = HashMap::new();
+        let mut blocks: HashMap<String, Vec<String>> = HashMap::new();
+        let mut state = ParseState::Outside;
+
+        for line in dump.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            let indent = line.len() - line.trim_start().len();
+
+            // Headers only start a block at or above the current header's depth,
+            // so bracketed metadata inside a block is never mistaken for one
+            let header_allowed = match state {
+                ParseState::Outside => true,
+                ParseState::Package { indent: header, .. } => indent <= header,
+            };
+            if header_allowed && let Some(caps) = PACKAGE_HEADER_RE.captures(trimmed) {
+                if only.is_some() && !blocks.is_empty() {
+                    break;
+                }
+                let name = caps[1].to_string();
+                if only.is_none_or(|p| p == name) {
+                    blocks.entry(name.clone()).or_default().push(line.to_string());
+                }
+                state = ParseState::Package { name, indent, in_secondary: false, path: None };
+                continue;
+            }
+
+            let ParseState::Package { ref name, indent: header, ref mut in_secondary, ref mut path } = state else {
+                continue;
+            };
+            // A shallower line, or a section title level with the header, ends the block
+            if indent < header || (indent == header && trimmed.ends_with(':')) {
+                state = ParseState::Outside;
+                continue;
+            }
+            if let Some(block) = blocks.get_mut(name) {
+                block.push(line.to_string());
+            }
+
+            if let Some(apk) = trimmed.strip_prefix("path:") {
+                *in_secondary = false;
+                *path = Some(apk.trim().to_string());
+                continue;
+            } else if trimmed.contains("secondary dex") {
+                *in_secondary = true;
+                continue;
+            } else if trimmed.starts_with('/') {
+                // Bare dex path; anything that isn't an APK lives outside the install
+                *in_secondary |= !trimmed.ends_with(".apk");
+                *path = Some(trimmed.to_string());
+                continue;
+            } else if !STATUS_RE.is_match(trimmed) || only.is_some_and(|p| p != name) {
+                continue;
+            }
+
+            let status = Self::capture(&FILTER_EXTRACT_RE, trimmed).unwrap_or_else(|| "unknown".to_string());
+            let status_raw = Self::capture(&STATUS_RAW_RE, trimmed).unwrap_or_else(|| status.clone());
+
+            results.entry(name.clone()).or_default().push(DexOptInfo {
+                raw_line: trimmed.to_string(),
+                abi: Abi::from_line(trimmed),
+                status,
+                status_raw,
+                reason: Self::capture(&REASON_EXTRACT_RE, trimmed),
+                is_secondary: *in_secondary,
+                version: Self::capture(&VERSION_EXTRACT_RE, trimmed),
+                version_mismatch: false,
+                compiled_at: Self::capture(&COMPILED_AT_RE, trimmed),
+                has_profile: PROFILE_RE.is_match(trimmed),
+                path: path.clone(),
+            });
+        }
+
+        let mut analyzer = Analyzer { results, blocks };
+        analyzer.flag_version_mismatches();
+        analyzer
+    }
+
+    fn capture(re: &Regex, line: &str) -> Option<String> {
+        re.captures(line).and_then(|c| c.get(1)).map(|m| m.as_str().to_string())
+    }
+
+    /// Returns the version shared by most versioned entries, if any carry one.
+    pub fn majority_version(&self) -> Option<String> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for info in self.results.values().flatten() {
+            if let Some(ref v) = info.version {
+                *counts.entry(v).or_insert(0) += 1;
+            }
+        }
+        counts.into_iter().max_by_key(|(_, n)| *n).map(|(v, _)| v.to_string())
+    }
+
+    fn flag_version_mismatches(&mut self) {
+        let Some(majority) = self.majority_version() else {
+            return;
+        };
+        for info in self.results.values_mut().flatten() {
+            info.version_mismatch = info.version.as_ref().is_some_and(|v| *v != majority);
+        }
+    }
+
+    /// Fills in `compiled_at` from artifact mtimes for primary entries the dump
+    /// didn't timestamp. Only meaningful when the APKs are on this filesystem.
+    pub fn fill_artifact_times(&mut self, packages: &[Package]) {
+        for pkg in packages {
+            let Some(infos) = self.results.get_mut(&pkg.name) else {
+                continue;
+            };
+            for info in infos.iter_mut().filter(|i| i.compiled_at.is_none() && !i.is_secondary) {
+                info.compiled_at = pkg
+                    .artifact_mtime(info.abi)
+                    .map(|t| humantime::format_rfc3339_seconds(t).to_string());
+            }
+        }
+    }
+
+    /// Interprets the statuses for a device on `api_level`. Dumps from
+    /// Android 7 (API 25) and earlier use the pre-Oreo filter names, which are
+    /// rewritten to their current equivalents (`raw_line` keeps the original).
+    /// Returns entry counts of the statuses that level shouldn't report (see
+    /// [`Status::expected_on`]), keyed by the status as written.
+    pub fn apply_api_level(&mut self, api_level: u32) -> BTreeMap<String, usize> {
+        let mut unexpected = BTreeMap::new();
+        for info in self.results.values_mut().flatten() {
+            if api_level < 26
+                && let Some(status) = Status::from_legacy(&info.status)
+            {
+                info.status = status.as_str().to_string();
+            } else if !info.status_kind().expected_on(api_level) {
+                *unexpected.entry(info.status.clone()).or_insert(0) += 1;
+            }
+        }
+        unexpected
+    }
+
+    /// Packages the dump has entries for, in no particular order.
+    pub fn package_names(&self) -> impl Iterator<Item = &str> {
+        self.results.keys().map(String::as_str)
+    }
+
+    /// The untouched lines of `pkg_name`'s block (`--raw`), header first.
+    pub fn raw_block(&self, pkg_name: &str) -> Option<&[String]> {
+        self.blocks.get(pkg_name).map(Vec::as_slice)
+    }
+
+    /// Entries for `pkg_name`, in dump order.
+    pub fn get_info(&self, pkg_name: &str) -> Option<&Vec<DexOptInfo>> {
+        self.results.get(pkg_name)
+    }
+
+    /// Heuristic for a dump that was cut short, typically because the caller
+    /// lacks the privileges `dumpsys` needs to report dexopt state.
+    pub fn looks_truncated(&self, dump: &str, package_count: usize) -> bool {
+        const MIN_DUMP_LINES: usize = 3;
+        dump.lines().filter(|l| !l.trim().is_empty()).count() < MIN_DUMP_LINES
+            || (self.results.is_empty() && package_count > 0)
+    }
+}
+
+/// Where the package list and the dump disagree, usually because they were
+/// captured at different times (`--reconcile`).
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct Reconciliation<'a> {
+    /// In the dump but not installed: stale data or leftovers of an uninstall.
+    pub orphaned: Vec<&'a str>,
+    /// Listed but absent from the dump, e.g. installed after it was taken.
+    pub no_data: Vec<&'a str>,
+}
+
+impl<'a> Reconciliation<'a> {
+    /// Checks `listed` (the packages in scope) against the dump, and the dump
+    /// against `installed` (every package, whatever the scope). Both lists
+    /// come out sorted.
+    pub fn compute(analyzer: &'a Analyzer, listed: &'a [Package], installed: &'a [Package]) -> Self {
+        let known: HashSet<&str> = installed.iter().map(|p| p.name.as_str()).collect();
+        let mut orphaned: Vec<&str> = analyzer.package_names().filter(|name| !known.contains(name)).collect();
+        orphaned.sort_unstable();
+        let mut no_data: Vec<&str> =
+            listed.iter().map(|p| p.name.as_str()).filter(|name| analyzer.get_info(name).is_none()).collect();
+        no_data.sort_unstable();
+        Reconciliation { orphaned, no_data }
+    }
+}
+
+/// An entry whose status claims compiled code that isn't on disk
+/// (`--verify-artifacts`), e.g. after the artifacts were pruned.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MissingArtifact<'a> {
+    pub package: &'a str,
+    pub abi: Abi,
+    pub status: &'a str,
+}
+
+impl<'a> MissingArtifact<'a> {
+    /// Checks the primary entries of `rows` whose status compiles code (see
+    /// [`Status::compiles_code`]) against the filesystem; secondary dex files
+    /// and entries without a known ABI are skipped.
+    pub fn find(rows: &[DisplayRow<'a>], dalvik_cache: &Path) -> Vec<Self> {
+        let mut missing = Vec::new();
+        for (pkg, _, info_list) in rows {
+            for info in info_list.iter().copied().flatten().filter(|i| !i.is_secondary && i.status_kind().compiles_code()) {
+                if pkg.has_compiled_artifact(info.abi, dalvik_cache) == Some(false) {
+                    missing.push(MissingArtifact { package: &pkg.name, abi: info.abi, status: &info.status });
+                }
+            }
+        }
+        missing
+    }
+}
+
+/// A package's statuses in a baseline dump and in the current one.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct StatusChange<'a> {
+    pub package: &'a str,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Per-package status transitions between a baseline dump and the current one.
+#[derive(Debug, Default, Serialize)]
+pub struct StatusDiff<'a> {
+    pub improved: Vec<StatusChange<'a>>,
+    pub regressed: Vec<StatusChange<'a>>,
+    /// Packages whose statuses differ but whose least optimized entry ranks
+    /// the same (`extract -> verify`, or an ABI entry added or dropped).
+    pub changed: Vec<StatusChange<'a>>,
+    /// Packages whose statuses match the baseline (`before == after`).
+    pub unchanged: Vec<StatusChange<'a>>,
+}
+
+impl<'a> StatusDiff<'a> {
+    /// Compares the displayed packages against `baseline`, ranking changes
+    /// by [`package_score`], the score of the least optimized entry, as
+    /// sorting does.
+    pub fn compute(rows: &[DisplayRow<'a>], baseline: &'a Analyzer) -> Self {
+        let mut diff = StatusDiff::default();
+        for (pkg, _, info_list) in rows {
+            let old_infos = baseline.get_info(&pkg.name);
+            let before = Self::status_list(old_infos);
+            let after = Self::status_list(*info_list);
+            if before == after {
+                diff.unchanged.push(StatusChange { package: &pkg.name, before, after });
+                continue;
+            }
+
+            let change = StatusChange { package: &pkg.name, before, after };
+            match package_score(*info_list).cmp(&package_score(old_infos)) {
+                std::cmp::Ordering::Greater => diff.improved.push(change),
+                std::cmp::Ordering::Less => diff.regressed.push(change),
+                std::cmp::Ordering::Equal => diff.changed.push(change),
+            }
+        }
+        diff
+    }
+
+    /// All statuses of a package in dump order, e.g. `verify/run-from-apk`.
+    pub fn status_list(info_list: Option<&Vec<DexOptInfo>>) -> Option<String> {
+        let infos = info_list?;
+        Some(infos.iter().map(|i| i.status.as_str()).collect::<Vec<_>>().join("/"))
+    }
+}
+
+/// AOSP's `pm.dexopt.<reason>` values, for devices that don't set their own
+/// and for saved dumps.
+const AOSP_DEFAULT_FILTERS: &[(&str, &str)] = &[
+    ("first-boot", "verify"),
+    ("boot-after-ota", "verify"),
+    ("boot-after-mainline-update", "verify"),
+    ("install", "speed-profile"),
+    ("install-fast", "skip"),
+    ("install-bulk", "speed-profile"),
+    ("install-bulk-secondary", "verify"),
+    ("install-bulk-downgraded", "verify"),
+    ("install-bulk-secondary-downgraded", "verify"),
+    ("bg-dexopt", "speed-profile"),
+    ("ab-ota", "speed-profile"),
+    ("inactive", "verify"),
+    ("cmdline", "verify"),
+    ("shared", "speed"),
+];
+
+/// The compiler filter the system intends for each compilation reason.
+pub struct DefaultFilters {
+    by_reason: HashMap<String, String>,
+    /// Whether any value came from the device's properties.
+    pub from_device: bool,
+}
+
+/// An entry whose filter differs from the default for its reason.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Deviation<'a> {
+    pub package: &'a str,
+    pub abi: Abi,
+    pub reason: &'a str,
+    pub status: &'a str,
+    pub expected: String,
+}
+
+impl DefaultFilters {
+    pub fn aosp() -> Self {
+        let by_reason = AOSP_DEFAULT_FILTERS.iter().map(|(r, f)| (r.to_string(), f.to_string())).collect();
+        DefaultFilters { by_reason, from_device: false }
+    }
+
+    /// The AOSP defaults, overridden by the device's `pm.dexopt.*`
+    /// properties when `getprop` can read them.
+    pub fn fetch(shell: &Shell) -> Self {
+        let mut defaults = Self::aosp();
+        if let Ok(output) = shell.output(&mut shell.command("getprop", &[]))
+            && output.status.success()
+        {
+            defaults.apply_getprop(&String::from_utf8_lossy(&output.stdout));
+        }
+        defaults
+    }
+
+    /// Takes the `[pm.dexopt.<reason>]: [<filter>]` lines of `getprop` output.
+    pub fn apply_getprop(&mut self, raw: &str) {
+        for line in raw.lines() {
+            let Some((key, value)) = line.trim().split_once("]: [") else {
+                continue;
+            };
+            if let Some(reason) = key.strip_prefix("[pm.dexopt.")
+                && let Some(filter) = value.strip_suffix(']')
+                && !filter.is_empty()
+            {
+                self.by_reason.insert(reason.to_string(), filter.to_string());
+                self.from_device = true;
+            }
+        }
+    }
+
+    /// The filter `reason` should produce, or `None` when it has no default
+    /// or the system skips compilation for it.
+    pub fn expected(&self, reason: &str) -> Option<&str> {
+        self.by_reason.get(reason).map(String::as_str).filter(|f| *f != "skip")
+    }
+
+    /// Primary-dex entries of `rows` whose status isn't the default for their
+    /// reason. Entries without a reason, or with one that has no default
+    /// (`prebuilt`, ...), can't deviate.
+    pub fn deviations<'a>(&self, rows: &[DisplayRow<'a>]) -> Vec<Deviation<'a>> {
+        let mut deviations = Vec::new();
+        for (pkg, _, info_list) in rows {
+            for info in info_list.iter().copied().flatten().filter(|i| !i.is_secondary) {
+                let Some(reason) = info.reason.as_deref() else {
+                    continue;
+                };
+                if let Some(expected) = self.expected(reason)
+                    && expected != info.status
+                {
+                    deviations.push(Deviation {
+                        package: &pkg.name,
+                        abi: info.abi,
+                        reason,
+                        status: &info.status,
+                        expected: expected.to_string(),
+                    });
+                }
+            }
+        }
+        deviations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyzer_parsing() {
+        let sample_dump = r#"
+[com.example.app]
+  arm64: [status=speed-profile] [reason=bg-dexopt] [primary-abi]
+[com.system.service]
+  arm64: [status=verify] [reason=prebuilt]
+"#;
+        let analyzer = Analyzer::new(sample_dump);
+
+        let info_app = analyzer.get_info("com.example.app").unwrap();
+        assert_eq!(info_app.len(), 1);
+        assert_eq!(info_app[0].status, "speed-profile");
+
+        let info_sys = analyzer.get_info("com.system.service").unwrap();
+        assert_eq!(info_sys.len(), 1);
+        assert_eq!(info_sys[0].status, "verify");
+        assert_eq!(info_sys[0].reason.as_deref(), Some("prebuilt"));
+        assert_eq!(info_app[0].reason.as_deref(), Some("bg-dexopt"));
+
+        assert!(analyzer.get_info("non.existent").is_none());
+    }
+
+    #[test]
+    fn test_version_mismatch_flagging() {
+        let sample_dump = r#"
+[com.a.app]
+  arm64: [status=speed-profile] [reason=bg-dexopt] [dexopt-version=34]
+[com.b.app]
+  arm64: [status=speed-profile] [reason=bg-dexopt] [dexopt-version=34]
+[com.c.app]
+  arm64: [status=verify] [reason=install] [dexopt-version=33]
+[com.d.app]
+  arm64: [status=verify] [reason=install]
+"#;
+        let analyzer = Analyzer::new(sample_dump);
+        assert_eq!(analyzer.majority_version().as_deref(), Some("34"));
+
+        let a = &analyzer.get_info("com.a.app").unwrap()[0];
+        assert_eq!(a.version.as_deref(), Some("34"));
+        assert!(!a.version_mismatch);
+        assert!(analyzer.get_info("com.c.app").unwrap()[0].version_mismatch);
+        let d = &analyzer.get_info("com.d.app").unwrap()[0];
+        assert_eq!(d.version, None);
+        assert!(!d.version_mismatch);
+    }
+
+    #[test]
+    fn test_truncated_dump_detection() {
+        let empty = Analyzer::new("");
+        assert!(empty.looks_truncated("", 10));
+
+        let denied = "Permission Denial: can't dump package from pid=1234\n\n";
+        assert!(Analyzer::new(denied).looks_truncated(denied, 10));
+
+        let full = "[com.example.app]\n  path: /data/app/base.apk\n    arm64: [status=speed] [reason=cmdline]\n";
+        assert!(!Analyzer::new(full).looks_truncated(full, 1));
+    }
+
+    #[test]
+    fn test_compiled_at_parsing() {
+        let dump = "[com.a]\n  arm64: [status=speed] [reason=bg-dexopt] [last-modified=2026-03-01 04:12:09]\n[com.b]\n  arm64: [status=verify]\n";
+        let analyzer = Analyzer::new(dump);
+        assert_eq!(analyzer.get_info("com.a").unwrap()[0].compiled_at.as_deref(), Some("2026-03-01 04:12:09"));
+        assert_eq!(analyzer.get_info("com.b").unwrap()[0].compiled_at, None);
+    }
+
+    #[test]
+    fn test_profile_present_but_unused_is_optimizable() {
+        let sample_dump = r#"
+  [com.example.app]
+    path: /data/app/~~abc==/com.example.app-xyz==/base.apk
+      arm64: [status=run-from-apk] [reason=install] [profile-present]
+      arm: [status=verify] [reason=install]
+  [com.example.done]
+    path: /data/app/~~def==/com.example.done-uvw==/base.apk
+      arm64: [status=speed-profile] [reason=bg-dexopt] [has-profile]
+"#;
+        let analyzer = Analyzer::new(sample_dump);
+
+        let infos = analyzer.get_info("com.example.app").unwrap();
+        assert!(infos[0].has_profile && infos[0].is_optimizable());
+        assert!(!infos[1].has_profile && !infos[1].is_optimizable());
+        // Already compiled with its profile
+        let done = &analyzer.get_info("com.example.done").unwrap()[0];
+        assert!(done.has_profile && !done.is_optimizable());
+    }
+
+    #[test]
+    fn test_recommended_filter() {
+        assert_eq!(recommended_filter(AppType::User, Some("install"), "verify"), Some("speed-profile"));
+        assert_eq!(recommended_filter(AppType::User, Some("bg-dexopt"), "speed-profile"), None);
+        // Prebuilt system apps keep their verify unless the background job ran
+        assert_eq!(recommended_filter(AppType::System, Some("prebuilt"), "verify"), None);
+        assert_eq!(recommended_filter(AppType::System, Some("bg-dexopt"), "verify"), Some("speed-profile"));
+        // Without a reason only the catch-all rule applies
+        assert_eq!(recommended_filter(AppType::System, None, "run-from-apk"), Some("verify"));
+        assert_eq!(recommended_filter(AppType::User, None, "speed"), None);
+    }
+
+    #[test]
+    fn test_secondary_dex_parsing() {
+        let sample_dump = r#"
+  [com.example.app]
+    path: /data/app/~~abc==/com.example.app-xyz==/base.apk
+      arm64: [status=speed-profile] [reason=bg-dexopt] [primary-abi]
+    known secondary dex files:
+      /data/user/0/com.example.app/code_cache/plugin.dex
+        arm64: [status=verify] [reason=bg-dexopt] [primary-abi]
+  [com.other.app]
+    path: /data/app/~~def==/com.other.app-uvw==/base.apk
+      arm64: [status=speed] [reason=cmdline]
+"#;
+        let analyzer = Analyzer::new(sample_dump);
+
+        let infos = analyzer.get_info("com.example.app").unwrap();
+        assert_eq!(infos.len(), 2);
+        assert!(!infos[0].is_secondary);
+        assert!(infos[1].is_secondary);
+        assert_eq!(infos[1].status, "verify");
+
+        let other = analyzer.get_info("com.other.app").unwrap();
+        assert!(!other[0].is_secondary);
+    }
+
+    #[test]
+    fn test_reconciliation() {
+        let analyzer = Analyzer::new(
+            "[com.kept]\n  arm64: [status=speed]\n[com.gone]\n  arm64: [status=verify]\n[com.system]\n  arm64: [status=speed]\n",
+        );
+        let user = [Package::new("com.kept", "/data/app/a/base.apk"), Package::new("com.new", "/data/app/b/base.apk")];
+        let all: Vec<Package> = user.iter().cloned().chain([Package::new("com.system", "/system/app/S/S.apk")]).collect();
+
+        let rec = Reconciliation::compute(&analyzer, &user, &all);
+        assert_eq!(rec, Reconciliation { orphaned: vec!["com.gone"], no_data: vec!["com.new"] });
+        // Without the full list, system packages would look orphaned
+        assert_eq!(Reconciliation::compute(&analyzer, &user, &user).orphaned, ["com.gone", "com.system"]);
+    }
+
+    #[test]
+    fn test_api_level_interpretation() {
+        let dump = "[com.old]\n  arm: [status=interpret-only] [reason=install]\n\
+                    [com.new]\n  arm64: [status=quicken]\n  arm64: [status=speed-profile]\n";
+        let mut modern = Analyzer::new(dump);
+        let unexpected = modern.apply_api_level(34);
+        assert_eq!(unexpected, BTreeMap::from([("interpret-only".to_string(), 1), ("quicken".to_string(), 1)]));
+        assert_eq!(modern.get_info("com.old").unwrap()[0].status, "interpret-only");
+        assert_eq!(Analyzer::new(dump).apply_api_level(29), BTreeMap::from([("interpret-only".to_string(), 1)]));
+
+        let mut nougat = Analyzer::new(dump);
+        // quicken wasn't a filter name yet; interpret-only was
+        assert_eq!(nougat.apply_api_level(24), BTreeMap::from([("quicken".to_string(), 1)]));
+        let old = &nougat.get_info("com.old").unwrap()[0];
+        assert_eq!(old.status, "quicken");
+        assert!(old.raw_line.contains("interpret-only"));
+    }
+
+    #[test]
+    fn test_known_statuses_match_status_enum() {
+        assert!(KNOWN_STATUSES.iter().all(|name| name.parse::<Status>().is_ok_and(|s| s.as_str() == *name)));
+        let analyzer = Analyzer::new("[com.a]\n  arm64: [status=speed-profile]\n  arm: [status=turbo]\n");
+        let kinds: Vec<Status> = analyzer.get_info("com.a").unwrap().iter().map(|i| i.status_kind()).collect();
+        assert_eq!(kinds, [Status::SpeedProfile, Status::Unknown]);
+    }
+
+    #[test]
+    fn test_line_regexes() {
+        // A pattern broken by an edit fails here rather than on first use on a device
+        for re in [&STATUS_RE, &FILTER_EXTRACT_RE, &STATUS_RAW_RE, &REASON_EXTRACT_RE, &COMPILED_AT_RE, &VERSION_EXTRACT_RE, &PROFILE_RE, &PACKAGE_HEADER_RE] {
+            Lazy::force(re);
+        }
+
+        let filter = |line| Analyzer::capture(&FILTER_EXTRACT_RE, line);
+        assert_eq!(filter("arm64: [status=run-from-apk]").as_deref(), Some("run-from-apk"));
+        assert_eq!(filter("arm64: [filter=speed-profile,reason=install]").as_deref(), Some("speed-profile"));
+        assert_eq!(filter("arm: [status=verify][reason=install]").as_deref(), Some("verify"));
+        assert_eq!(filter("arm64: status=quicken reason=install").as_deref(), Some("quicken"));
+        assert_eq!(filter("arm64: [status=]"), None);
+        // Only whole keys count
+        assert_eq!(filter("arm64: [substatus=speed]"), None);
+
+        let raw = |line| Analyzer::capture(&STATUS_RAW_RE, line);
+        assert_eq!(raw("arm64: [status=Speed-Profile,location=ota] [reason=install]").as_deref(), Some("Speed-Profile,location=ota"));
+        assert_eq!(raw("arm: [status=verify][reason=install]").as_deref(), Some("verify"));
+        let analyzer = Analyzer::new("[com.a]\n  arm64: [status=speed-profile,location=ota]\n  arm: [status=verify]\n");
+        let infos = analyzer.get_info("com.a").unwrap();
+        assert_eq!((infos[0].status.as_str(), infos[0].status_raw.as_str()), ("speed-profile", "speed-profile,location=ota"));
+        assert_eq!(infos[1].status_raw, "verify");
+
+        let reason = |line| Analyzer::capture(&REASON_EXTRACT_RE, line);
+        assert_eq!(reason("arm64: [filter=speed-profile,reason=install]").as_deref(), Some("install"));
+        assert_eq!(reason("arm64: [status=speed] [reason=bg-dexopt]").as_deref(), Some("bg-dexopt"));
+        assert_eq!(reason("arm64: [reason=install-dm,foo]").as_deref(), Some("install-dm"));
+        assert_eq!(reason("arm64: [status=speed]"), None);
+
+        let isa = |line| Analyzer::capture(&STATUS_RE, line);
+        assert_eq!(isa("arm64: [status=speed]").as_deref(), Some("arm64"));
+        assert_eq!(isa("  x86_64: [status=speed]").as_deref(), Some("x86_64"));
+        assert_eq!(isa("x86: [status=speed]").as_deref(), Some("x86"));
+        assert_eq!(isa("path: /data/app/com.arm64app/base.apk"), None);
+        assert_eq!(isa("armv7: [status=speed]"), None);
+    }
+
+    #[test]
+    fn test_default_filter_deviations() {
+        let mut defaults = DefaultFilters::aosp();
+        assert_eq!(defaults.expected("install"), Some("speed-profile"));
+        assert_eq!(defaults.expected("install-fast"), None);
+        assert_eq!(defaults.expected("prebuilt"), None);
+
+        defaults.apply_getprop("[pm.dexopt.install]: [verify]\n[pm.dexopt.install]\n[ro.build.type]: [user]\n[pm.dexopt.shared]: []\n");
+        assert!(defaults.from_device);
+        assert_eq!(defaults.expected("install"), Some("verify"));
+        assert_eq!(defaults.expected("shared"), Some("speed"));
+
+        let dump = "[com.a]\n  arm64: [status=speed] [reason=install]\n  arm: [status=verify] [reason=install]\n  \
+                    known secondary dex files:\n    /data/user/0/com.a/x.dex\n      arm64: [status=run-from-apk] [reason=install]\n\
+                    [com.b]\n  arm64: [status=speed] [reason=prebuilt]\n  arm: [status=verify]\n";
+        let analyzer = Analyzer::new(dump);
+        let pkgs = [Package::new("com.a", ""), Package::new("com.b", "")];
+        let rows: Vec<DisplayRow> = pkgs.iter().map(|p| (p, None, analyzer.get_info(&p.name))).collect();
+        let deviations = defaults.deviations(&rows);
+        assert_eq!(
+            deviations,
+            [Deviation { package: "com.a", abi: Abi::Arm64, reason: "install", status: "speed", expected: "verify".to_string() }]
+        );
+    }
+
+    #[test]
+    fn test_abi_detection() {
+        assert_eq!(Abi::from_line("arm64: [status=verify]"), Abi::Arm64);
+        assert_eq!(Abi::from_line("arm: [status=verify]"), Abi::Arm);
+        assert_eq!(Abi::from_line("x86_64: [status=verify]"), Abi::X86_64);
+        assert_eq!(Abi::from_line("riscv64: [status=verify]"), Abi::Riscv64);
+        assert_eq!(Abi::from_line("[status=verify]"), Abi::Unknown);
+        assert_eq!(format!("{:<5}|", Abi::Arm), "arm  |");
+
+        let analyzer = Analyzer::new("[com.a]\n  arm64: [status=verify]\n");
+        assert_eq!(analyzer.get_info("com.a").unwrap()[0].details(), "[status=verify]");
+    }
+
+    #[test]
+    fn test_status_diff() {
+        let before = Analyzer::new("[com.a]\n  arm64: [status=verify]\n[com.b]\n  arm64: [status=speed]\n[com.c]\n  arm64: [status=speed]\n");
+        let after = Analyzer::new("[com.a]\n  arm64: [status=speed-profile]\n[com.b]\n  arm64: [status=run-from-apk]\n[com.c]\n  arm64: [status=speed]\n");
+        let pkgs: Vec<Package> = ["com.a", "com.b", "com.c"]
+            .iter()
+            .map(|n| Package::new(*n, ""))
+            .collect();
+        let rows: Vec<DisplayRow> = pkgs.iter().map(|p| (p, None, after.get_info(&p.name))).collect();
+
+        let diff = StatusDiff::compute(&rows, &before);
+        assert_eq!(
+            diff.improved,
+            vec![StatusChange {
+                package: "com.a",
+                before: Some("verify".to_string()),
+                after: Some("speed-profile".to_string())
+            }]
+        );
+        assert_eq!(
+            diff.regressed,
+            vec![StatusChange {
+                package: "com.b",
+                before: Some("speed".to_string()),
+                after: Some("run-from-apk".to_string())
+            }]
+        );
+        assert_eq!(diff.unchanged.len(), 1);
+        assert!(diff.changed.is_empty());
+        assert_eq!(
+            serde_json::to_value(&diff.regressed).unwrap(),
+            serde_json::json!([{ "package": "com.b", "before": "speed", "after": "run-from-apk" }])
+        );
+    }
+
+    #[test]
+    fn test_status_diff_equal_rank() {
+        // extract and verify score the same; the other package only lost its x86_64 entry
+        let before = Analyzer::new(
+            "[com.a]\n  arm64: [status=extract]\n\
+             [com.b]\n  arm64: [status=speed-profile]\n  arm: [status=speed-profile]\n  x86_64: [status=speed-profile]\n",
+        );
+        let after = Analyzer::new("[com.a]\n  arm64: [status=verify]\n[com.b]\n  arm64: [status=speed-profile]\n  arm: [status=speed-profile]\n");
+        let pkgs = [Package::new("com.a", ""), Package::new("com.b", "")];
+        let rows: Vec<DisplayRow> = pkgs.iter().map(|p| (p, None, after.get_info(&p.name))).collect();
+
+        let diff = StatusDiff::compute(&rows, &before);
+        assert!(diff.improved.is_empty());
+        assert!(diff.regressed.is_empty());
+        assert_eq!(
+            diff.changed,
+            vec![
+                StatusChange { package: "com.a", before: Some("extract".to_string()), after: Some("verify".to_string()) },
+                StatusChange {
+                    package: "com.b",
+                    before: Some("speed-profile/speed-profile/speed-profile".to_string()),
+                    after: Some("speed-profile/speed-profile".to_string())
+                },
+            ]
+        );
+        // The `baseline_diff` CI gates on: equal-rank changes never count as regressions
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["regressed"], serde_json::json!([]));
+        assert_eq!(json["improved"], serde_json::json!([]));
+        assert_eq!(json["changed"][0], serde_json::json!({ "package": "com.a", "before": "extract", "after": "verify" }));
+        assert_eq!(json["changed"].as_array().map(Vec::len), Some(2));
+    }
+}
@@ -0,0 +1,58 @@
+//! Bundled sample data for `--demo`: a package list and dexopt dump shaped
+//! like a real device's, for screenshots, documentation and smoke tests.
+
+/// `pm list packages -f -i` output of the demo device.
+pub const PACKAGES: &str = include_str!("demo/packages.txt");
+
+/// `dumpsys package dexopt` output of the demo device. One listed package
+/// (`com.google.android.calculator`) is left out to show missing data.
+pub const DEXOPT_DUMP: &str = include_str!("demo/dexopt.txt");
+
+/// Packages the demo device reports as disabled (`pm list packages -d`).
+pub const DISABLED: &[&str] = &["com.legacy.game"];
+
+const LABELS: &[(&str, &str)] = &[
+    ("com.google.android.apps.maps", "Maps"),
+    ("com.spotify.music", "Spotify"),
+    ("com.whatsapp", "WhatsApp"),
+    ("org.telegram.messenger", "Telegram"),
+    ("com.instagram.android", "Instagram"),
+    ("org.fdroid.fdroid", "F-Droid"),
+    ("org.mozilla.firefox", "Firefox"),
+    ("com.termux", "Termux"),
+    ("com.example.devbuild", "Dev Build"),
+    ("com.legacy.game", "Legacy Racer"),
+    ("com.android.chrome", "Chrome"),
+    ("com.android.settings", "Settings"),
+    ("com.android.systemui", "System UI"),
+    ("com.google.android.calculator", "Calculator"),
+    ("android", "Android System"),
+];
+
+/// Label of a demo package. Stands in for APK parsing, since none of the
+/// paths in [`PACKAGES`] exist on this machine.
+pub fn label(package: &str) -> Option<String> {
+    LABELS.iter().find(|(name, _)| *name == package).map(|(_, label)| label.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzer;
+    use crate::package::{AppType, Package};
+
+    #[test]
+    fn test_demo_data_is_consistent() {
+        let packages = Package::parse_list(PACKAGES);
+        let analyzer = Analyzer::new(DEXOPT_DUMP);
+        assert_eq!(packages.len(), LABELS.len());
+        assert!(packages.iter().all(|p| label(&p.name).is_some()), "every demo package has a label");
+        let missing: Vec<&str> =
+            packages.iter().filter(|p| analyzer.get_info(&p.name).is_none()).map(|p| p.name.as_str()).collect();
+        assert_eq!(missing, ["com.google.android.calculator"]);
+        assert!(packages.iter().any(|p| p.app_type == AppType::System));
+        assert!(packages.iter().any(|p| p.installer.is_none() && p.app_type == AppType::User));
+        assert!(DISABLED.iter().all(|name| packages.iter().any(|p| p.name == *name)));
+        assert_eq!(label("com.unknown"), None);
+    }
+}
@@ -0,0 +1,212 @@
+//! Environment self-test (`dexter doctor`): probes the tools a run depends
+//! on and the privileges it needs, so problems show up before a long audit.
+
+use std::io::{self, Write};
+use std::process::Command;
+
+use colored::Colorize;
+
+use crate::analyzer::Analyzer;
+use crate::package::AAPT_TOOLS;
+use crate::shell::{current_uid, sdk_level, Shell};
+
+/// UID of Android's `shell` user, which `adb shell` runs as.
+const SHELL_UID: u32 = 2000;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Pass,
+    /// Usable, but some output will be degraded (e.g. no app labels).
+    Warn,
+    /// A run would fail or report nothing.
+    Fail,
+}
+
+/// One line of the checklist.
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    /// Version, path or what went wrong.
+    pub detail: String,
+}
+
+impl Check {
+    pub fn new(name: &'static str, outcome: Outcome, detail: impl Into<String>) -> Self {
+        Check { name, outcome, detail: detail.into() }
+    }
+}
+
+/// The numeric UID in `id` output (`uid=0(root) gid=0(root) ...`).
+pub fn parse_uid(id: &str) -> Option<u32> {
+    let rest = id.trim().strip_prefix("uid=")?;
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).map_or(rest, |end| &rest[..end]);
+    digits.parse().ok()
+}
+
+/// First non-empty line a command printed, on stdout or stderr.
+fn first_line(output: &std::process::Output) -> Option<String> {
+    [&output.stdout, &output.stderr]
+        .into_iter()
+        .flat_map(|bytes| String::from_utf8_lossy(bytes).lines().map(str::trim).map(str::to_string).collect::<Vec<_>>())
+        .find(|line| !line.is_empty())
+}
+
+/// `adb` on this machine, which `--adb` runs every command through.
+pub fn adb() -> Check {
+    match Command::new("adb").arg("version").output() {
+        Ok(output) if output.status.success() => {
+            Check::new("adb", Outcome::Pass, first_line(&output).unwrap_or_else(|| "found".to_string()))
+        }
+        Ok(output) => Check::new("adb", Outcome::Fail, format!("'adb version' failed ({})", output.status)),
+        Err(e) => Check::new("adb", Outcome::Fail, format!("not found ({})", e)),
+    }
+}
+
+/// A required device tool, reported by where it lives.
+fn tool(shell: &Shell, name: &'static str) -> Check {
+    match shell.which(shell.program(name)) {
+        Some(path) => Check::new(name, Outcome::Pass, path),
+        None => Check::new(name, Outcome::Fail, "not found"),
+    }
+}
+
+/// The label tool: `aapt_path` when given, otherwise the first of [`AAPT_TOOLS`].
+/// Only labels depend on it, so a missing one is a warning.
+fn aapt(shell: &Shell, aapt_path: Option<&str>) -> Check {
+    let found = match aapt_path {
+        Some(path) => shell.which(path).map(|_| path),
+        None => AAPT_TOOLS.iter().copied().find(|tool| shell.which(tool).is_some()),
+    };
+    let Some(program) = found else {
+        let missing = match aapt_path {
+            Some(path) => format!("'{}' is not installed", path),
+            None => "neither aapt2 nor aapt is installed".to_string(),
+        };
+        return Check::new("aapt", Outcome::Warn, format!("{}; labels will be missing (pkg install aapt2, or --aapt-path)", missing));
+    };
+    let version = shell.output(&mut shell.command(program, &["version"])).ok().and_then(|o| first_line(&o));
+    Check::new("aapt", Outcome::Pass, format!("{}: {}", program, version.unwrap_or_else(|| "version unknown".to_string())))
+}
+
+/// Who commands run as: root locally, or at least the shell user over ADB.
+fn identity(shell: &Shell, over_adb: bool) -> Check {
+    let Some(id) = current_uid(shell) else {
+        return Check::new("privileges", Outcome::Fail, "'id' could not be run");
+    };
+    match parse_uid(&id) {
+        Some(0) => Check::new("privileges", Outcome::Pass, id),
+        Some(SHELL_UID) if over_adb => Check::new("privileges", Outcome::Pass, format!("{} (enough for dumpsys; --compile may need root)", id)),
+        _ if over_adb => Check::new("privileges", Outcome::Warn, format!("{}; dumpsys may report no dexopt state", id)),
+        _ => Check::new("privileges", Outcome::Fail, format!("{}; run under su or pass --cmd-prefix", id)),
+    }
+}
+
+/// The device's release, which decides which statuses are expected.
+fn release(shell: &Shell) -> Check {
+    match sdk_level(shell) {
+        Some(level) => Check::new("android", Outcome::Pass, format!("API {}", level)),
+        None => Check::new("android", Outcome::Warn, "couldn't read ro.build.version.sdk; pass --api-level"),
+    }
+}
+
+/// Reads the dexopt dump once, the way a run does.
+fn dump(shell: &Shell) -> Check {
+    match Analyzer::fetch_dump(shell, None) {
+        Ok(dump) => match Analyzer::new(&dump).package_names().count() {
+            0 => Check::new("dexopt dump", Outcome::Fail, "read, but it lists no packages (needs shell or root privileges)"),
+            n => Check::new("dexopt dump", Outcome::Pass, format!("{} packages with dexopt state", n)),
+        },
+        Err(e) => Check::new("dexopt dump", Outcome::Fail, e.to_string()),
+    }
+}
+
+/// Every check against the target reached through `shell`. `adb` is probed
+/// separately with [`adb`], since without it there is no target to reach.
+pub fn device(shell: &Shell, over_adb: bool, aapt_path: Option<&str>) -> Vec<Check> {
+    vec![
+        tool(shell, "pm"),
+        tool(shell, "dumpsys"),
+        aapt(shell, aapt_path),
+        identity(shell, over_adb),
+        release(shell),
+        dump(shell),
+    ]
+}
+
+/// Prints the checklist: one `[ OK ]`/`[WARN]`/`[FAIL]` line per check.
+pub fn print(out: &mut dyn Write, checks: &[Check]) -> io::Result<()> {
+    let width = checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+    writeln!(out, "{}", "Environment check".bold())?;
+    for check in checks {
+        let mark = match check.outcome {
+            Outcome::Pass => "[ OK ]".green().bold(),
+            Outcome::Warn => "[WARN]".yellow().bold(),
+            Outcome::Fail => "[FAIL]".red().bold(),
+        };
+        writeln!(out, "  {} {:<width$}  {}", mark, check.name, check.detail, width = width)?;
+    }
+    let failed = checks.iter().filter(|c| c.outcome == Outcome::Fail).count();
+    if failed == 0 {
+        writeln!(out, "\n{}", "Ready to run.".green().bold())
+    } else {
+        writeln!(out, "\n{}", format!("{} of {} checks failed.", failed, checks.len()).red().bold())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_uid() {
+        assert_eq!(parse_uid("uid=0(root) gid=0(root) groups=0(root)"), Some(0));
+        assert_eq!(parse_uid("uid=2000(shell)"), Some(2000));
+        assert_eq!(parse_uid("uid=10123"), Some(10123));
+        assert_eq!(parse_uid("gid=0"), None);
+    }
+
+    #[test]
+    fn test_device_checks() {
+        let dir = std::env::temp_dir().join(format!("dexter-doctor-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = |name: &str, body: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let pm = script("pm", "exit 0");
+        let dumpsys = script("dumpsys", "printf '[com.a]\\n  arm64: [status=speed]\\n'");
+        let id = script("id", "echo 'uid=2000(shell) gid=2000(shell)'");
+        let aapt2 = script("aapt2", "echo 'Android Asset Packaging Tool (aapt) 2.19'");
+        let shell = Shell::local()
+            .with_prefix("env")
+            .with_program("pm", &pm)
+            .with_program("dumpsys", &dumpsys)
+            .with_program("id", &id)
+            .with_program("getprop", &script("getprop", "echo 34"));
+
+        let checks = device(&shell, true, Some(&aapt2));
+        let outcome = |name: &str| checks.iter().find(|c| c.name == name).map(|c| (c.outcome, c.detail.clone())).unwrap();
+        assert_eq!(outcome("pm"), (Outcome::Pass, pm.clone()));
+        assert_eq!(outcome("aapt").0, Outcome::Pass);
+        assert!(outcome("aapt").1.ends_with("(aapt) 2.19"), "{:?}", outcome("aapt"));
+        assert_eq!(outcome("privileges").0, Outcome::Pass);
+        assert_eq!(outcome("android").1, "API 34");
+        assert_eq!(outcome("dexopt dump"), (Outcome::Pass, "1 packages with dexopt state".to_string()));
+
+        // The shell user isn't enough locally, and a missing tool fails
+        let local = shell.clone().with_program("dumpsys", &dir.join("missing").to_string_lossy());
+        let checks = device(&local, false, Some(&dir.join("no-aapt").to_string_lossy()));
+        assert!(checks.iter().filter(|c| c.outcome == Outcome::Fail).map(|c| c.name).eq(["dumpsys", "privileges", "dexopt dump"]));
+        assert_eq!(checks.iter().find(|c| c.name == "aapt").unwrap().outcome, Outcome::Warn);
+
+        let mut out = Vec::new();
+        print(&mut out, &checks).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("3 of 6 checks failed."), "{}", text);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+//! Error types shared by the analysis core.
+
+use std::io;
+use std::path::PathBuf;
+
+/// Failures from the analysis core, so callers can match on the failure mode
+/// instead of inspecting message strings.
+#[derive(Debug, thiserror::Error)]
+pub enum DexoptError {
+    /// An external command (`pm`, `dumpsys`, `adb`, ...) could not be run or timed out.
+    #[error("Failed to execute '{command}': {source}")]
+    CommandFailed {
+        command: String,
+        #[source]
+        source: io::Error,
+    },
+    /// A required device tool (`pm`, `dumpsys`) doesn't exist on the target.
+    #[error("'{program}' not found. Are you running on an Android device, or did you mean to use --adb?")]
+    ToolMissing { program: String },
+    /// An external command ran but exited unsuccessfully.
+    #[error("'{command}' failed ({status}){}", stderr_suffix(stderr))]
+    CommandExit {
+        command: String,
+        status: String,
+        /// First line of the command's stderr, if it printed any.
+        stderr: String,
+    },
+    /// A saved capture could not be read.
+    #[error("Failed to read {what} from {}: {source}", path.display())]
+    FileRead {
+        what: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// A file (such as the label cache) could not be written.
+    #[error("Failed to write {what} to {}: {source}", path.display())]
+    FileWrite {
+        what: &'static str,
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// Data could not be serialized as JSON.
+    #[error("Failed to serialize {what} as JSON: {source}")]
+    Serialize {
+        what: &'static str,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// User-supplied input (such as a filter pattern) could not be parsed.
+    #[error("Invalid {what} '{input}': {reason}")]
+    ParseError {
+        what: &'static str,
+        input: String,
+        reason: String,
+    },
+    /// No usable ADB device could be selected.
+    #[error("{0}")]
+    NoDevice(String),
+    /// An APK could not be opened or parsed for its label.
+    #[error("Failed to read APK {}: {reason}", path.display())]
+    ApkRead { path: PathBuf, reason: String },
+}
+
+fn stderr_suffix(stderr: &str) -> String {
+    if stderr.is_empty() { String::new() } else { format!(": {}", stderr) }
+}
+
+impl DexoptError {
+    pub(crate) fn command(command: &str, source: io::Error) -> Self {
+        DexoptError::CommandFailed { command: command.to_string(), source }
+    }
+}
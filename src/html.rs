@@ -0,0 +1,236 @@
+//! Standalone HTML report (`--format html`): inline CSS and a few lines of
+//! script for column sorting, so the file can be shared as is.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use colored::Color;
+
+use crate::analyzer::{Abi, Dimension};
+use crate::package::AppType;
+use crate::status::Status;
+use crate::ui::{DisplayRow, MonoStyle, SummaryOrder, Theme, UI};
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.4em; }
+.meta { color: #666; }
+table { border-collapse: collapse; margin: 1em 0; }
+th, td { border: 1px solid #ccc; padding: 4px 10px; text-align: left; }
+th { background: #f0f0f0; }
+#packages th { cursor: pointer; user-select: none; }
+#packages th::after { content: ' \\2195'; color: #999; }
+td.num { text-align: right; }
+.missing { color: #c62828; font-style: italic; }
+";
+
+/// Sorts `#packages` by the clicked column, using `data-sort` when a cell
+/// has it (statuses sort by how optimized they are, not alphabetically).
+const SCRIPT: &str = "
+document.querySelectorAll('#packages th').forEach((th, col) => {
+  th.addEventListener('click', () => {
+    const body = th.closest('table').tBodies[0];
+    const asc = th.dataset.order !== 'asc';
+    th.dataset.order = asc ? 'asc' : 'desc';
+    const key = row => {
+      const cell = row.cells[col];
+      return cell.dataset.sort !== undefined ? Number(cell.dataset.sort) : cell.textContent.toLowerCase();
+    };
+    [...body.rows]
+      .sort((a, b) => (key(a) > key(b) ? 1 : key(a) < key(b) ? -1 : 0) * (asc ? 1 : -1))
+      .forEach(row => body.appendChild(row));
+  });
+});
+";
+
+/// Escapes text for use in HTML element content and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Inline CSS that mirrors how `theme` paints `status` in the terminal.
+fn status_css(theme: Theme, status: &str) -> String {
+    let Some(color) = theme.status_color(status) else {
+        return match Theme::mono_style(status) {
+            MonoStyle::Bold => "font-weight: bold".to_string(),
+            MonoStyle::Underline => "text-decoration: underline".to_string(),
+            MonoStyle::Plain => String::new(),
+        };
+    };
+    let css = match color {
+        Color::Green => "#2e7d32".to_string(),
+        Color::Yellow => "#b58900".to_string(),
+        Color::Blue => "#1565c0".to_string(),
+        Color::Red => "#c62828".to_string(),
+        Color::Magenta => "#8e24aa".to_string(),
+        Color::Cyan => "#00838f".to_string(),
+        Color::Black => "#000000".to_string(),
+        Color::BrightBlack => "#757575".to_string(),
+        Color::BrightRed => "#e53935".to_string(),
+        Color::BrightGreen => "#43a047".to_string(),
+        Color::BrightYellow => "#f9a825".to_string(),
+        Color::BrightBlue => "#1e88e5".to_string(),
+        Color::BrightMagenta => "#d81b60".to_string(),
+        Color::BrightCyan => "#00acc1".to_string(),
+        Color::TrueColor { r, g, b } => format!("rgb({}, {}, {})", r, g, b),
+        // The terminal's default foreground
+        _ => return String::new(),
+    };
+    let weight = if Status::classify(status) == Status::Error { "; font-weight: bold" } else { "" };
+    format!("color: {}{}", css, weight)
+}
+
+fn status_cell(theme: Theme, status: &str) -> String {
+    format!(
+        "<td data-sort=\"{}\" style=\"{}\">{}</td>",
+        UI::status_score(status),
+        status_css(theme, status),
+        escape(status)
+    )
+}
+
+/// Renders the full report. With `summary_only`, the package table is left
+/// out (`--quiet`).
+pub fn render(
+    rows: &[DisplayRow],
+    stats: &BTreeMap<String, usize>,
+    app_type: AppType,
+    summary_by: Dimension,
+    order: SummaryOrder,
+    theme: Theme,
+    summary_only: bool,
+) -> String {
+    let total_apps = rows.len();
+    let missing_data = rows.iter().filter(|row| row.2.is_none()).count();
+    let errors = rows.iter().filter(|row| UI::has_error(row.2)).count();
+    let generated = humantime::format_rfc3339_seconds(SystemTime::now());
+
+    let mut html = String::new();
+    // Writing to a String can't fail
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Dexopt report</title>\n<style>{}</style>\n</head>\n<body>\n\
+         <h1>Dexopt analysis report</h1>\n<p class=\"meta\">Generated {} by dexter {}</p>\n",
+        STYLE,
+        generated,
+        env!("CARGO_PKG_VERSION")
+    );
+
+    let _ = write!(
+        html,
+        "<h2>Summary</h2>\n<table>\n<tr><th>App scope</th><td>{}</td></tr>\n\
+         <tr><th>Total apps checked</th><td class=\"num\">{}</td></tr>\n\
+         <tr><th>Missing data</th><td class=\"num\">{}</td></tr>\n\
+         <tr><th>Apps with errors</th><td class=\"num\">{}</td></tr>\n</table>\n",
+        app_type, total_apps, missing_data, errors
+    );
+    let dimension = match summary_by {
+        Dimension::Status => "Status",
+        Dimension::Reason => "Reason",
+    };
+    let _ = write!(html, "<table>\n<tr><th>{}</th><th>Entries</th><th>Share of entries</th></tr>\n", dimension);
+    // Entries are per ISA and dex file, so shares are of all entries, not apps
+    let entries: usize = stats.values().sum();
+    if stats.is_empty() {
+        html.push_str("<tr><td colspan=\"3\" class=\"missing\">No profile data found.</td></tr>\n");
+    }
+    for (key, count) in UI::ordered_stats(stats, order) {
+        let style = if summary_by == Dimension::Status { status_css(theme, key) } else { String::new() };
+        let _ = writeln!(
+            html,
+            "<tr><td style=\"{}\">{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+            style,
+            escape(key),
+            count,
+            UI::format_percentage(count, entries)
+        );
+    }
+    html.push_str("</table>\n");
+
+    if !summary_only {
+        html.push_str(
+            "<h2>Packages</h2>\n<table id=\"packages\">\n<thead><tr><th>Package</th><th>Label</th>\
+             <th>Type</th><th>ABI</th><th>Status</th><th>Reason</th></tr></thead>\n<tbody>\n",
+        );
+        for (pkg, label, info_list) in rows {
+            let prefix = format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td>",
+                escape(&pkg.name),
+                escape(label.as_deref().unwrap_or("")),
+                pkg.app_type
+            );
+            match info_list {
+                Some(infos) => {
+                    for info in infos.iter() {
+                        let abi = match info.abi {
+                            Abi::Unknown => String::new(),
+                            abi => abi.to_string(),
+                        };
+                        let _ = writeln!(
+                            html,
+                            "{}<td>{}</td>{}<td>{}</td></tr>",
+                            prefix,
+                            escape(&abi),
+                            status_cell(theme, &info.status),
+                            escape(info.reason.as_deref().unwrap_or(""))
+                        );
+                    }
+                }
+                None => {
+                    let _ = writeln!(
+                        html,
+                        "{}<td></td><td data-sort=\"{}\" class=\"missing\">(no dexopt data)</td><td></td></tr>",
+                        prefix,
+                        UI::package_score(None)
+                    );
+                }
+            }
+        }
+        html.push_str("</tbody>\n</table>\n");
+        let _ = writeln!(html, "<script>{}</script>", SCRIPT);
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzer;
+    use crate::package::Package;
+
+    #[test]
+    fn test_html_report() {
+        let analyzer = Analyzer::new("[com.a]\n  arm64: [status=speed] [reason=install]\n");
+        let pkgs = [Package::new("com.a", "/data/app/a/base.apk"), Package::new("com.b", "/data/app/b/base.apk")];
+        let rows: Vec<DisplayRow> = vec![
+            (&pkgs[0], Some("<Tom & Jerry>".to_string()), analyzer.get_info("com.a")),
+            (&pkgs[1], None, analyzer.get_info("com.b")),
+        ];
+        let stats = BTreeMap::from([("speed".to_string(), 1)]);
+
+        let html = render(&rows, &stats, AppType::User, Dimension::Status, SummaryOrder::Name, Theme::Default, false);
+        assert!(html.contains("&lt;Tom &amp; Jerry&gt;"));
+        assert!(html.contains("<td data-sort=\"3\" style=\"color: #2e7d32\">speed</td>"));
+        assert!(html.contains("(no dexopt data)"));
+        assert!(html.contains("<td class=\"num\">2</td>"), "total apps");
+
+        let summary = render(&rows, &stats, AppType::User, Dimension::Status, SummaryOrder::Name, Theme::Mono, true);
+        assert!(!summary.contains("id=\"packages\""));
+        assert!(summary.contains("font-weight: bold"));
+    }
+}
@@ -0,0 +1,20 @@
+//! Core of `dexter`: fetching and parsing Android dexopt state, resolving app
+//! labels, and rendering reports. The `dexter` binary is a thin CLI over this.
+
+pub mod analyzer;
+pub mod demo;
+pub mod doctor;
+pub mod error;
+pub mod html;
+pub mod package;
+pub mod prometheus;
+pub mod shell;
+pub mod status;
+pub mod tui;
+pub mod ui;
+
+pub use analyzer::{Abi, Analyzer, DexOptInfo, Dimension};
+pub use error::DexoptError;
+pub use package::{AppType, Package};
+pub use shell::Shell;
+pub use status::{Severity, Status};
@@ -1,465 +1,402 @@
-use apk_info::Apk;
 use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
+use clap::builder::PossibleValuesParser;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use colored::*;
+use dexter::{demo, doctor, html, prometheus};
+use dexter::doctor::{Check, Outcome};
+use dexter::analyzer::{Abi, Analyzer, DefaultFilters, DexOptInfo, Dimension, MissingArtifact, Reconciliation, StatusDiff, COMPILER_FILTERS, KNOWN_STATUSES};
+use dexter::package::{compile_all, write_atomic, AppType, CompileOutcome, LabelCache, NameMatcher, Package, DALVIK_CACHE};
+use dexter::shell::{current_uid, sdk_level, Shell};
+use dexter::status::Status;
+use dexter::ui::{color_enabled, csv_escape, stats_line, toml_string, DisplayRow, Glyphs, OrderedBuffer, Summary, SummaryOrder, Theme, TypeSummary, ABI_COLUMN_WIDTH, UI};
 use rayon::prelude::*;
-use regex::Regex;
-use std::collections::{BTreeMap, HashMap};
-use std::fmt;
-use std::io::{self, Write};
-use std::process::{Command, Stdio};
-use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 use serde_json::json;
-use once_cell::sync::Lazy;
 use unicode_width::UnicodeWidthStr;
-use terminal_size::{Width, terminal_size};
+
+/// Exit code contract, shown in `--help`.
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0  Success (no package matched --fail-on)
+  1  Runtime error, or a failed `doctor` check
+  2  At least one displayed package matched --fail-on";
+
+/// Process exit code when `--fail-on` matches a displayed package.
+const EXIT_POLICY_VIOLATION: i32 = 2;
 
 /// A tool to analyze dexopt status on Android devices.
 #[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, after_help = EXIT_CODES_HELP)]
 struct Args {
-    /// Filter packages by name (substring match)
+    /// Filter packages by name (substring match; comma-separated values match any)
     #[arg(short, long)]
     filter: Option<String>,
 
-    /// Filter by specific dexopt status (e.g., 'speed', 'verify', 'error')
-    #[arg(short, long)]
-    status: Option<String>,
+    /// Filter by app label (case-insensitive substring; resolves labels for every package)
+    #[arg(short, long, value_name = "TEXT")]
+    label_filter: Option<String>,
+
+    /// Exclude packages whose name matches this pattern (repeatable)
+    #[arg(short = 'x', long, value_name = "PATTERN")]
+    exclude: Vec<String>,
+
+    /// Filter by glob on the whole name (e.g., 'com.google.*', '*.camera'; comma-separated values match any). Combined with --filter, both must match
+    #[arg(short = 'g', long, value_delimiter = ',', value_name = "PATTERN")]
+    glob: Vec<String>,
+
+    /// Only packages listed in FILE: one exact name or glob per line, '#' starts a comment line. Combined with the other filters, all must match
+    #[arg(long, value_name = "FILE")]
+    include_file: Option<PathBuf>,
+
+    /// Exclude packages listed in FILE (same format as --include-file)
+    #[arg(long, value_name = "FILE")]
+    exclude_file: Option<PathBuf>,
+
+    /// Analyze only this exact package (of any type), skipping every other block of the dump
+    #[arg(short = 'p', long, value_name = "NAME", conflicts_with_all = ["filter", "glob", "exclude", "include_file", "exclude_file", "path_filter", "type", "show_missing", "updated_system"])]
+    package: Option<String>,
+
+    /// Filter by install path (substring match, e.g. '/data/app' or '/mnt/expand'; comma-separated values match any). Combined with name filters, both must match
+    #[arg(long, value_delimiter = ',', value_name = "SUBSTR")]
+    path_filter: Vec<String>,
+
+    /// Only system apps with an update installed under /data/app (implies --type system; needs a live package list)
+    #[arg(long, conflicts_with_all = ["type", "package_file"])]
+    updated_system: bool,
+
+    /// Skip disabled packages, whose dexopt state rarely matters (needs a live package list)
+    #[arg(long, conflicts_with = "package_file")]
+    enabled_only: bool,
+
+    /// Filter by installing package (e.g. 'com.android.vending' for the Play Store; 'none' matches apps with no installer on record, such as adb installs; comma-separated values match any)
+    #[arg(long, value_delimiter = ',', value_name = "NAME")]
+    installer: Vec<String>,
+
+    /// Interpret --filter and --exclude patterns as regular expressions (--filter is not split on commas)
+    #[arg(long)]
+    regex: bool,
+
+    /// Filter by dexopt status, comma-separated (e.g., 'speed', 'verify,run-from-apk')
+    #[arg(short, long, value_delimiter = ',')]
+    status: Vec<String>,
+
+    /// Filter by compilation reason, comma-separated (e.g., 'ab-ota', 'install,bg-dexopt'); 'unknown' matches entries without one
+    #[arg(long, value_delimiter = ',')]
+    reason: Vec<String>,
+
+    /// Show only packages whose best status ranks below STATUS (e.g. 'speed-profile')
+    #[arg(long, value_name = "STATUS", value_parser = PossibleValuesParser::new(KNOWN_STATUSES))]
+    min_status: Option<String>,
 
     /// Type of applications to analyze
     #[arg(short, long, value_enum, default_value_t = AppType::User)]
     r#type: AppType,
 
+    /// List the packages installed for this user (e.g. a work profile) instead of the current one
+    #[arg(long, value_name = "ID")]
+    user: Option<u32>,
+
     /// Show detailed information for each package
     #[arg(short, long)]
     verbose: bool,
 
-    /// Output results as JSON
-    #[arg(short, long)]
-    json: bool,
+    /// List only packages the dexopt dump has no data for
+    #[arg(long, conflicts_with_all = ["status", "min_status", "abi_filter"])]
+    show_missing: bool,
 
-    /// Optimize application(s). Use 'all' for background dexopt job, or specify a package name.
-    #[arg(short = 'o', long = "optimize")]
-    optimize: Option<String>,
-}
+    /// Show only packages with no ahead-of-time compiled code for ISA, including ones with no ISA entries at all
+    #[arg(long, value_name = "ISA", value_parser = PossibleValuesParser::new(Abi::ALL.iter().filter_map(|abi| abi.isa())))]
+    abi_filter: Option<String>,
 
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
-enum AppType {
-    User,
-    System,
-    All,
-}
+    /// Order in which packages are displayed
+    #[arg(long, value_enum, default_value_t = SortBy::Name)]
+    sort: SortBy,
 
-impl fmt::Display for AppType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let name = match self {
-            AppType::User => "User",
-            AppType::System => "System",
-            AppType::All => "All",
-        };
-        write!(f, "{}", name)
-    }
-}
+    /// Print package names bucketed under one section per status (or reason)
+    #[arg(long, value_enum, value_name = "DIMENSION")]
+    group_by: Option<Dimension>,
 
-#[derive(Debug, Clone, Serialize)]
-struct Package {
-    name: String,
-    path: String,
-}
+    /// Show only the N least optimized packages (implies --sort status)
+    #[arg(long, value_name = "N")]
+    top: Option<usize>,
 
-impl Package {
-    /// Fetches the package list using `pm list packages`.
-    fn fetch_list(app_type: AppType) -> Result<Vec<Self>> {
-        let filter_flag = match app_type {
-            AppType::User => "-3",
-            AppType::System => "-s",
-            AppType::All => "",
-        };
+    /// Process only the first N matching packages, in the chosen --sort order; labels are resolved just for those unless a label filter or sort needs them all
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    limit: Option<usize>,
 
-        let mut cmd = Command::new("pm");
-        cmd.arg("list").arg("packages").arg("-f");
-        if !filter_flag.is_empty() {
-            cmd.arg(filter_flag);
-        }
+    /// Dimension used for the summary breakdown
+    #[arg(long, value_enum, default_value_t = Dimension::Status)]
+    summary_by: Dimension,
 
-        let output = cmd.output()
-            .with_context(|| "Failed to execute 'pm' command")?;
+    /// Order of the summary breakdown lines
+    #[arg(long, value_enum, value_name = "BY", default_value_t = SummaryOrder::Name)]
+    sort_summary: SummaryOrder,
 
-        let raw = String::from_utf8_lossy(&output.stdout);
-        let mut list = Vec::new();
+    /// Disable colored output (also honors the NO_COLOR environment variable)
+    #[arg(long)]
+    no_color: bool,
 
-        for line in raw.lines() {
-            if let Some(p) = line.trim().strip_prefix("package:") {
-                if let Some((path, name)) = p.rsplit_once('=') {
-                    list.push(Package {
-                        name: name.trim().to_string(),
-                        path: path.trim().to_string(),
-                    });
-                }
-            }
-        }
+    /// Draw boxes and bars with ASCII only (also chosen automatically for TERM=dumb or a non-UTF-8 locale)
+    #[arg(long)]
+    ascii: bool,
 
-        list.sort_by(|a, b| a.name.cmp(&b.name));
-        Ok(list)
-    }
+    /// Palette for statuses: 'colorblind' avoids red/green, 'mono' uses only bold/underline
+    #[arg(long, value_enum, default_value_t = Theme::Default)]
+    theme: Theme,
 
-    /// Gets the application label from the APK file.
-    fn get_label(&self) -> Option<String> {
-        // 1. aapt: resolves string resources directly from the APK — most accurate
-        if let Some(label) = self.get_label_from_aapt() {
-            return Some(label);
-        }
+    /// Override one status's color, e.g. 'verify=cyan', 'speed=bright_green' or 'error=#ff8800' (repeatable; applies to every theme)
+    #[arg(long = "color", value_name = "STATUS=COLOR", value_parser = Theme::parse_override)]
+    colors: Vec<(Status, Color)>,
 
-        // 2. apk-info native parsing: fast fallback when aapt is unavailable
-        if let Ok(apk) = Apk::new(&self.path) {
-            if let Some(label) = apk.get_application_label() {
-                let clean = label.trim().replace(['\r', '\n'], " ");
-                if !clean.is_empty() && Self::is_valid_label(&clean) {
-                    return Some(clean);
-                }
-            }
-        }
+    /// Exit with code 2 if any displayed package has one of these statuses, comma-separated
+    #[arg(long, value_delimiter = ',', value_name = "STATUS")]
+    fail_on: Vec<String>,
 
-        None
-    }
+    /// Only print the summary (with `--format json`/`yaml`, only the summary object; with `toml`, only the `[summary]` table)
+    #[arg(short, long)]
+    quiet: bool,
 
-    /// Returns true if the string looks like a real human-readable app label.
-    /// Rejects resource refs, class names, URLs, JSON blobs, and other garbage
-    /// that some APKs mistakenly store in the application-label field.
-    fn is_valid_label(label: &str) -> bool {
-        // Resource reference e.g. "@0x1040001"
-        if label.starts_with('@') {
-            return false;
-        }
-        // URL e.g. "https://www.facebook.com/.well-known/..."
-        if label.starts_with("http://") || label.starts_with("https://") {
-            return false;
-        }
-        // JSON blob e.g. "[{ \"include\": ... }]" or "{ ... }"
-        if label.starts_with('[') || label.starts_with('{') {
-            return false;
-        }
-        // Bare package/class name e.g. "com.facebook.katana" or "com.foo.MainActivity"
-        let is_class_like = label.contains('.')
-            && !label.contains(' ')
-            && label.chars().all(|c| c.is_alphanumeric() || c == '.' || c == '_' || c == '$');
-        if is_class_like {
-            return false;
-        }
-        // Suspiciously long strings are almost certainly not a real label
-        if label.len() > 64 {
-            return false;
-        }
-        true
-    }
+    /// After all other output, print one plain `apps=N entries=M <status>=<count> ...` line for scripts (the counts are of entries)
+    #[arg(long, conflicts_with_all = ["format", "json", "tui"])]
+    stats_line: bool,
 
-    fn get_label_from_aapt(&self) -> Option<String> {
-        let output = Command::new("aapt")
-            .arg("dump")
-            .arg("badging")
-            .arg(&self.path)
-            .output()
-            .ok()?;
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 
-        if !output.status.success() {
-            return None;
-        }
+    /// Write the report to this file instead of stdout, without colors; progress and warnings stay on stderr
+    #[arg(short = 'O', long, value_name = "PATH")]
+    output: Option<PathBuf>,
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        for line in stdout.lines() {
-            let trimmed = line.trim();
-            if let Some(label) = trimmed.strip_prefix("application-label:'") {
-                if let Some(end) = label.find('\'') {
-                    return Some(label[..end].to_string());
-                }
-            }
-        }
-        None
-    }
+    /// Append to the --output file instead of overwriting it
+    #[arg(long, requires = "output")]
+    append: bool,
 
-    fn is_aapt_available() -> bool {
-        Command::new("which")
-            .arg("aapt")
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .map(|s| s.success())
-            .unwrap_or(false)
-    }
-}
+    /// Also write the summary (totals and breakdowns) as JSON to this file, replacing it atomically
+    #[arg(long, value_name = "PATH")]
+    summary_json: Option<PathBuf>,
 
-#[derive(Debug, Clone, Serialize)]
-struct DexOptInfo {
-    raw_line: String,
-    status: String,
-}
+    /// Print only the matched package names, each followed by a NUL byte, for `xargs -0`: no colors, progress or summary
+    #[arg(long, conflicts_with_all = ["format", "json", "quiet", "verbose", "tui", "watch", "group_by", "baseline", "compare_to_default", "raw", "stats_line", "explain"])]
+    print0: bool,
 
-struct Analyzer {
-    results: HashMap<String, Vec<DexOptInfo>>,
-}
+    /// Browse the results in an interactive terminal UI
+    #[arg(long, conflicts_with_all = ["format", "json", "quiet", "group_by", "baseline", "compile", "output"])]
+    tui: bool,
 
-static STATUS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(arm64:|arm:)").expect("Invalid regex for status"));
-static FILTER_EXTRACT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(?:status|filter)=([^\]\s]+)").expect("Invalid regex for filter extraction"));
+    /// After each package, print its block from the dexopt dump verbatim (text and JSON), for parser bug reports
+    #[arg(long)]
+    raw: bool,
 
-impl Analyzer {
-    fn fetch_dump() -> Result<String> {
-        let output = Command::new("dumpsys")
-            .arg("package")
-            .arg("dexopt")
-            .output()?;
-        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
-    }
+    /// Fill the CSV status column with the whole status attribute (e.g. `speed-profile,location=...`) instead of just the filter name; JSON always carries it as `status_raw`
+    #[arg(long)]
+    raw_status: bool,
 
-    fn new(dump: &str) -> Self {
-        let mut results: HashMap<String, Vec<DexOptInfo>> = HashMap::new();
-        let mut current_pkg: Option<String> = None;
+    /// After the text report, explain each status it shows: what it means for speed and storage
+    #[arg(long)]
+    explain: bool,
 
-        for line in dump.lines() {
-            let trimmed = line.trim();
-            if trimmed.is_empty() {
-                continue;
-            }
+    /// Below the summary, count entries per status and ABI (rows by status, a column per instruction set); `abi_matrix` in the JSON summary
+    #[arg(long)]
+    count_abis: bool,
 
-            if trimmed.starts_with('[')
-                && trimmed.ends_with(']')
-                && !trimmed.contains(' ')
-                && !trimmed.contains('=')
-            {
-                current_pkg = Some(trimmed[1..trimmed.len() - 1].to_string());
-            } else if let Some(ref pkg) = current_pkg {
-                if STATUS_RE.is_match(trimmed) {
-                    let status = FILTER_EXTRACT_RE
-                        .captures(trimmed)
-                        .and_then(|c| c.get(1))
-                        .map(|m| m.as_str().to_string())
-                        .unwrap_or_else(|| "unknown".to_string());
-
-                    results.entry(pkg.clone()).or_default().push(DexOptInfo {
-                        raw_line: trimmed.to_string(),
-                        status,
-                    });
-                }
-            }
-        }
+    /// Show the size of each package's compiled artifacts (.odex/.vdex/.art) and their total (text and JSON)
+    #[arg(long)]
+    show_sizes: bool,
 
-        Analyzer { results }
-    }
+    /// Suggest, next to each status, the compiler filter the app should be on when it differs (text output)
+    #[arg(long)]
+    compiler_filter_recommendation: bool,
 
-    fn get_info(&self, pkg_name: &str) -> Option<&Vec<DexOptInfo>> {
-        self.results.get(pkg_name)
-    }
-}
+    /// Clear the screen and re-run the analysis every N seconds until interrupted
+    #[arg(long, value_name = "SECONDS", conflicts_with_all = ["tui", "json", "compile"])]
+    watch: Option<u64>,
 
-struct UI;
+    /// Output results as JSON (shorthand for `--format json`)
+    #[arg(short, long)]
+    json: bool,
 
-impl UI {
-    fn get_status_color(status: &str) -> Color {
-        match status {
-            "speed-profile" | "speed" => Color::Green,
-            "verify" => Color::Yellow,
-            "quicken" => Color::Blue,
-            "run-from-apk" | "error" => Color::Red,
-            "everything" => Color::Magenta,
-            _ => Color::White,
-        }
-    }
+    /// Optimize application(s). Use 'all' for background dexopt job, or specify a package name.
+    #[arg(short = 'o', long = "optimize", value_name = "TARGET")]
+    optimize: Option<String>,
 
-    fn colorize_line(line: &str, status: &str) -> String {
-        let color = Self::get_status_color(status);
-        if status == "error" {
-            line.color(color).bold().to_string()
-        } else {
-            line.color(color).to_string()
-        }
-    }
+    /// Recompile displayed packages that are below the given compiler filter (e.g., 'speed-profile')
+    #[arg(long, value_name = "FILTER")]
+    compile: Option<String>,
 
-    fn print_header() {
-        println!(
-            "\n{} | {}\n",
-            format!("{:<45}", "Package").bold().underline(),
-            format!("{:<30}", "DexOpt Status").bold().underline()
-        );
-    }
+    /// Print the commands --compile would run and the expected filter changes, without running them
+    #[arg(long, requires = "compile")]
+    dry_run: bool,
 
-    fn print_block_entry(
-        stdout: &mut io::Stdout,
-        pkg: &Package,
-        app_label: Option<&str>,
-        info_list: Option<&Vec<DexOptInfo>>,
-    ) -> io::Result<()> {
-        let min_width: usize = 40;
+    /// Skip the confirmation prompt for --compile
+    #[arg(short = 'y', long, requires = "compile")]
+    yes: bool,
 
-        let max_term_width = if let Some((Width(w), _)) = terminal_size() {
-            (w as usize).saturating_sub(4)
-        } else {
-            120
-        };
+    /// Persist resolved labels to this JSON file and reuse them on later runs, until the APK's size or time changes (read with `stat` on the device under --adb)
+    #[arg(long, value_name = "PATH")]
+    cache: Option<PathBuf>,
 
-        // Build plain (no ANSI) display name for accurate width measurement
-        let full_display_name = match app_label {
-            Some(label) => format!("{} ({})", label, pkg.name),
-            None => pkg.name.clone(),
-        };
+    /// Ignore and don't update the label cache, even if --cache is given
+    #[arg(long)]
+    no_cache: bool,
 
-        // Unicode-aware truncation
-        let display_name: String = if full_display_name.width() > max_term_width {
-            let mut truncated = String::new();
-            let mut w = 0usize;
-            for c in full_display_name.chars() {
-                let cw = UnicodeWidthStr::width(c.to_string().as_str());
-                if w + cw > max_term_width.saturating_sub(3) {
-                    truncated.push_str("...");
-                    break;
-                }
-                truncated.push(c);
-                w += cw;
-            }
-            truncated
-        } else {
-            full_display_name
-        };
+    /// Resolve at most N labels, or run at most N --compile jobs, at once [default: min(CPUs, 4)]
+    #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    jobs: Option<usize>,
 
-        // content_width = visual width of the plain text (no ANSI)
-        let content_width = display_name.width();
-        // box_width = number of ─ chars; actual rendered line is │ + ─*box_width + │
-        let box_width = (content_width + 4).max(min_width).min(max_term_width);
+    /// Kill device commands (pm, dumpsys, aapt, ...) that run longer than this
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
 
-        let border = "─".repeat(box_width);
-        writeln!(stdout, "{}", format!("┌{}┐", border).cyan())?;
+    /// Retry `pm list packages` and `dumpsys` up to N times, with backoff, when they fail or print nothing
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
 
-        // Padding: p_l + content_width + p_r == box_width (inner space between │ │)
-        let p_space = box_width.saturating_sub(content_width);
-        let p_l = p_space / 2;
-        let p_r = p_space - p_l;
+    /// Run every device command under this wrapper (e.g., 'su -c'); a wrapper ending in -c gets the command line as one argument
+    #[arg(long, value_name = "STRING")]
+    cmd_prefix: Option<String>,
 
-        // Build colorized version — ANSI bytes don't affect terminal column positions
-        let inner_content = if display_name.ends_with("...") {
-            display_name.bold().bright_white().to_string()
-        } else {
-            match app_label {
-                Some(_) => {
-                    let pkg_suffix = format!(" ({})", pkg.name);
-                    if display_name.ends_with(&pkg_suffix) {
-                        let label_part = &display_name[..display_name.len() - pkg_suffix.len()];
-                        format!(
-                            "{} ({})",
-                            label_part.bold().cyan(),
-                            pkg.name.bold().bright_white()
-                        )
-                    } else {
-                        // Suffix was truncated — colour whole string
-                        display_name.bold().bright_white().to_string()
-                    }
-                }
-                None => display_name.bold().bright_white().to_string(),
-            }
-        };
+    /// Path to the `pm` binary on the device
+    #[arg(long, value_name = "PATH")]
+    pm_path: Option<String>,
 
-        writeln!(
-            stdout,
-            "{}{}{}{}",
-            "│".cyan(),
-            " ".repeat(p_l),
-            inner_content,
-            format!("{}{}", " ".repeat(p_r), "│").cyan()
-        )?;
+    /// Path to the `dumpsys` binary on the device
+    #[arg(long, value_name = "PATH")]
+    dumpsys_path: Option<String>,
 
-        writeln!(stdout, "{}", format!("└{}┘", border).cyan())?;
+    /// Path to `aapt` or `aapt2` for label lookups [default: aapt2, else aapt, from PATH]
+    #[arg(long, value_name = "PATH")]
+    aapt_path: Option<String>,
 
-        if let Some(infos) = info_list {
-            let max_prefix_len = infos
-                .iter()
-                .filter_map(|i| i.raw_line.find(':'))
-                .max()
-                .unwrap_or(0);
+    /// Run commands on a device connected over ADB instead of the local shell
+    #[arg(long)]
+    adb: bool,
 
-            for info in infos {
-                let raw_line = if info.raw_line.width() > max_term_width {
-                    let mut s = info.raw_line.chars().take(max_term_width - 3).collect::<String>();
-                    s.push_str("...");
-                    s
-                } else {
-                    info.raw_line.clone()
-                };
+    /// Serial of the ADB device to use (required when several are attached)
+    #[arg(long, requires = "adb")]
+    serial: Option<String>,
 
-                let formatted = if let Some(idx) = raw_line.find(':') {
-                    let (prefix, rest) = raw_line.split_at(idx);
-                    format!("{:width$}{}", prefix, rest, width = max_prefix_len)
-                } else {
-                    raw_line
-                };
-                writeln!(stdout, "  {}", Self::colorize_line(&formatted, &info.status))?;
-            }
-        } else {
-            writeln!(stdout, "  {}", "(no info found)".italic().red())?;
-        }
-        writeln!(stdout)?;
-        Ok(())
-    }
+    /// Read a saved `dumpsys package dexopt` capture instead of querying the device
+    #[arg(long, value_name = "PATH")]
+    dump_file: Option<PathBuf>,
 
-    fn print_summary(total_apps: usize, stats: &BTreeMap<String, usize>, app_type: AppType) {
-        let width = 47;
-        let b_blue = Color::BrightBlue;
-        let b_yellow = Color::BrightYellow;
+    /// Compare against an earlier `dumpsys package dexopt` capture and report status changes (colored in text, a `baseline_diff` object with improved/regressed/changed/unchanged arrays in JSON and YAML; `changed` holds changes that leave the least optimized entry at the same rank)
+    #[arg(long, value_name = "FILE")]
+    baseline: Option<PathBuf>,
 
-        println!("\n\n{}", format!("╔{}╗", "═".repeat(width)).color(b_blue));
+    /// Report packages the dump has data for that aren't installed, and installed ones it has no data for
+    #[arg(long, conflicts_with = "package")]
+    reconcile: bool,
 
-        let title = "DEXOPT ANALYSIS SUMMARY";
-        let p_s = (width - title.len()) / 2;
-        let p_e = width - title.len() - p_s;
-        println!(
-            "{}{}{}{}",
-            "║".color(b_blue),
-            " ".repeat(p_s),
-            title.bold().color(b_yellow),
-            format!("{}{}", " ".repeat(p_e), "║").color(b_blue)
-        );
+    /// Warn about entries whose status says compiled (speed, speed-profile, ...) but whose oat file is missing on disk. Needs a live run on the device
+    #[arg(long, conflicts_with_all = ["dump_file", "package_file", "adb", "demo"])]
+    verify_artifacts: bool,
 
-        let mid = format!("╠{}╣", "═".repeat(width)).color(b_blue);
-        println!("{}", mid);
-
-        Self::add_summary_line("App Scope", &app_type.to_string(), Color::Cyan, Color::Magenta, width);
-        Self::add_summary_line("Total Apps Checked", &total_apps.to_string(), Color::Cyan, Color::BrightGreen, width);
-
-        println!("{}", mid);
-        let sub = "Profile Breakdown";
-        let p_s = (width - sub.len()) / 2;
-        let p_e = width - sub.len() - p_s;
-        println!(
-            "{}{}{}{}",
-            "║".color(b_blue),
-            " ".repeat(p_s),
-            sub.dimmed().bold(),
-            format!("{}{}", " ".repeat(p_e), "║").color(b_blue)
-        );
-        println!("{}", mid);
+    /// Report only the entries whose filter differs from the system default for their compilation reason
+    #[arg(long, conflicts_with_all = ["group_by", "baseline"])]
+    compare_to_default: bool,
 
-        if stats.is_empty() {
-            let msg = "No profile data found.";
-            let padding = " ".repeat(width.saturating_sub(2 + msg.len()));
-            println!("{}  {}{}{}", "║".color(b_blue), msg, padding, "║".color(b_blue));
-        } else {
-            for (profile, count) in stats {
-                let color = Self::get_status_color(profile);
-                Self::add_summary_line(profile, &count.to_string(), Color::Cyan, color, width);
-            }
-        }
-        println!("{}", format!("╚{}╝", "═".repeat(width)).color(b_blue));
-    }
+    /// Read a saved `pm list packages -f` capture (add `-i` for --installer) instead of querying the device
+    #[arg(long, value_name = "PATH")]
+    package_file: Option<PathBuf>,
 
-    fn add_summary_line(label: &str, value: &str, l_col: Color, v_col: Color, width: usize) {
-        let l_part = format!("{:<22}", label).bold().color(l_col);
-        let v_part = value.bold().color(v_col);
-        let padding = " ".repeat(width.saturating_sub(5 + 22 + value.len()));
-        println!(
-            "{}  {} : {}{}{}",
-            "║".color(Color::BrightBlue),
-            l_part,
-            v_part,
-            padding,
-            "║".color(Color::BrightBlue)
-        );
+    /// Interpret the dump as coming from a device on this Android API level (e.g. 34) [default: probed from the device, unset for --dump-file]
+    #[arg(long, value_name = "LEVEL", value_parser = clap::builder::RangedU64ValueParser::<u32>::new().range(1..))]
+    api_level: Option<u32>,
+
+    /// Run against the bundled sample device instead of this one (screenshots, CI smoke tests)
+    #[arg(long, hide = true, conflicts_with_all = ["dump_file", "package_file", "adb", "user", "compile", "optimize"])]
+    demo: bool,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Print a shell completion script to stdout
+    #[command(hide = true)]
+    Completions {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
+    },
+    /// Check that the tools and privileges a run needs are in place (honors --adb, --cmd-prefix and the *-path options)
+    Doctor,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum Format {
+    Text,
+    Json,
+    Csv,
+    Yaml,
+    /// One `--format json` package object per line, flushed as it is written
+    Jsonl,
+    /// Self-contained page with a sortable package table and the summary
+    Html,
+    /// `[[package]]` tables and a `[summary]` table, with the `--format json` fields (fields without a value are left out)
+    Toml,
+    /// Prometheus text exposition (gauges per app type and status) for node_exporter's textfile collector; --output is replaced atomically
+    Prometheus,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum SortBy {
+    /// Alphabetically by package name
+    Name,
+    /// Least optimized first
+    Status,
+    /// Alphabetically by app label, then package name. Resolves labels in
+    /// every view (slower); packages without one follow, by name
+    Label,
+}
+
+/// Writes the completion script for `shell`. `--status` and `--fail-on` accept
+/// any string at runtime, so their known values are only attached here.
+fn print_completions(shell: clap_complete::Shell) {
+    let statuses = || PossibleValuesParser::new(KNOWN_STATUSES);
+    let mut cmd = Args::command()
+        .mut_arg("status", |a| a.value_parser(statuses()))
+        .mut_arg("fail_on", |a| a.value_parser(statuses()));
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+}
+
+/// Asks the user to confirm a destructive action on stdin.
+fn confirm(prompt: &str) -> Result<bool> {
+    if !io::stdin().is_terminal() {
+        anyhow::bail!("Refusing to continue without confirmation; pass --yes to proceed non-interactively.");
     }
+    eprint!("{} [y/N] ", prompt);
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Name and status filters compiled once from the command line.
+struct Matchers {
+    include: Vec<NameMatcher>,
+    globs: Vec<NameMatcher>,
+    /// `--include-file` entries; empty when no file was given.
+    listed: Vec<NameMatcher>,
+    exclude: Vec<NameMatcher>,
+    paths: Vec<String>,
+    /// `--installer` names; `None` stands for `none`.
+    installers: Vec<Option<String>>,
+    status: Vec<String>,
+    /// `--reason` values, lowercased.
+    reasons: Vec<String>,
+    /// Score of `--min-status`; shown packages must rank below it.
+    below_score: Option<i32>,
+    /// `--abi-filter`; shown packages must lack optimized code for it.
+    abi_gap: Option<Abi>,
+    fail_on: Vec<String>,
 }
 
 fn check_root() -> Result<()> {
@@ -470,9 +407,103 @@ fn check_root() -> Result<()> {
     Ok(())
 }
 
+/// The `--show-sizes` continuation row of a package in table mode.
+fn print_size_row(out: &mut dyn Write, artifact_bytes: Option<Option<u64>>, pkg_width: usize) -> io::Result<()> {
+    let Some(bytes) = artifact_bytes else {
+        return Ok(());
+    };
+    let size = bytes.map_or_else(|| "none found".to_string(), UI::format_size);
+    let cells = [String::new(), String::new(), format!("Artifacts: {}", size).dimmed().to_string()];
+    writeln!(out, "{}", UI::table_row(&cells, &[pkg_width, ABI_COLUMN_WIDTH]))
+}
+
+/// Parallel label lookups and compiles when `--jobs` isn't given: one per
+/// CPU, capped at 4.
+fn default_jobs() -> usize {
+    const MAX_DEFAULT_JOBS: usize = 4;
+    std::thread::available_parallelism().map_or(1, |n| n.get().min(MAX_DEFAULT_JOBS))
+}
+
+/// Applies the timeout, retry, prefix and binary options to `shell`.
+fn configure_shell(shell: Shell, args: &Args) -> Shell {
+    let shell = shell.with_timeout(args.timeout.map(Duration::from_secs)).with_retries(args.retries);
+    let shell = if let Some(ref prefix) = args.cmd_prefix { shell.with_prefix(prefix) } else { shell };
+    let shell = if let Some(ref path) = args.pm_path { shell.with_program("pm", path) } else { shell };
+    if let Some(ref path) = args.dumpsys_path { shell.with_program("dumpsys", path) } else { shell }
+}
+
+/// `dexter doctor`: prints the environment checklist, exiting with 1 when a
+/// check failed. Runs without root, since reporting its absence is the point.
+fn doctor(args: &Args) -> Result<()> {
+    let mut checks = Vec::new();
+    if args.adb {
+        checks.push(doctor::adb());
+    }
+    let target = if args.adb { Shell::adb(args.serial.clone()) } else { Ok(Shell::local()) };
+    match target {
+        Ok(shell) => checks.extend(doctor::device(&configure_shell(shell, args), args.adb, args.aapt_path.as_deref())),
+        Err(e) => checks.push(Check::new("device", Outcome::Fail, e.to_string())),
+    }
+    doctor::print(&mut io::stdout(), &checks)?;
+    if checks.iter().any(|c| c.outcome == Outcome::Fail) {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
-    check_root()?;
     let mut args = Args::parse();
+    if let Some(Commands::Completions { shell }) = args.command {
+        print_completions(shell);
+        return Ok(());
+    }
+    // Files never get ANSI escapes
+    colored::control::set_override(color_enabled(args.no_color || args.output.is_some()));
+    Theme::set_overrides(args.colors.iter().copied().collect());
+    if let Some(Commands::Doctor) = args.command {
+        return doctor(&args);
+    }
+    // Label resolution and --compile are the parallel stages; bound them so a
+    // phone isn't flooded with aapt or dex2oat processes
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or_else(default_jobs))
+        .build_global()
+        .context("Failed to set up the worker thread pool")?;
+    // Fully offline runs only read captured files, ADB runs rely on the
+    // device's shell user, and a --cmd-prefix brings its own elevation
+    let offline = args.demo || (args.dump_file.is_some() && args.package_file.is_some());
+    if !offline && !args.adb && args.cmd_prefix.is_none() {
+        check_root()?;
+    }
+    let shell = configure_shell(if args.adb { Shell::adb(args.serial.clone())? } else { Shell::local() }, &args);
+    // Labels are looked up through the "aapt" program; point it at whichever tool is there
+    let aapt = if args.demo { None } else { args.aapt_path.as_deref().or_else(|| Package::find_aapt(&shell)) };
+    let shell = if let Some(path) = aapt { shell.with_program("aapt", path) } else { shell };
+    // A saved dump may come from another device, so only a live one is
+    // matched to this device's release
+    if args.api_level.is_none() && args.dump_file.is_none() && !args.demo {
+        args.api_level = sdk_level(&shell);
+    }
+    if args.json {
+        args.format = Format::Json;
+    }
+    if args.quiet && matches!(args.format, Format::Csv | Format::Jsonl) {
+        let name = if args.format == Format::Csv { "CSV" } else { "JSONL" };
+        anyhow::bail!("--quiet has no summary to print in {} format", name);
+    }
+    if args.watch.is_some() && !matches!(args.format, Format::Text | Format::Jsonl | Format::Prometheus) {
+        anyhow::bail!("--watch only supports the text, jsonl and prometheus formats");
+    }
+    if args.append && args.format == Format::Prometheus {
+        anyhow::bail!("--append would repeat metric families; prometheus output always replaces the file");
+    }
+
+    if args.package.is_some() {
+        args.r#type = AppType::All;
+    }
+    if args.updated_system {
+        args.r#type = AppType::System;
+    }
 
     if let Some(ref target) = args.optimize {
         args.verbose = true;
@@ -483,6 +514,62 @@ fn main() -> Result<()> {
 
     let prefix = "[-]".cyan();
 
+    if let Some(ref filter) = args.compile
+        && !COMPILER_FILTERS.contains(&filter.as_str())
+    {
+        anyhow::bail!(
+            "Unknown compiler filter '{}'. Expected one of: {}",
+            filter,
+            COMPILER_FILTERS.join(", ")
+        );
+    }
+
+    let normalize = |list: &[String]| -> Vec<String> { list.iter().map(|s| s.trim().to_lowercase()).collect() };
+    let status_filters = normalize(&args.status);
+    let reasons = normalize(&args.reason);
+    let fail_on = normalize(&args.fail_on);
+    let include = match args.filter.as_deref() {
+        Some(f) if args.regex => vec![NameMatcher::new(f, true)?],
+        Some(f) => f
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(|p| NameMatcher::new(p, false))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    let globs = args
+        .glob
+        .iter()
+        .map(|p| NameMatcher::glob(p.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut exclude = args
+        .exclude
+        .iter()
+        .map(|p| NameMatcher::new(p, args.regex))
+        .collect::<Result<Vec<_>, _>>()?;
+    let listed = match args.include_file {
+        Some(ref path) => {
+            let listed = NameMatcher::read_list(path, "include file")?;
+            if listed.is_empty() {
+                anyhow::bail!("{} lists no packages", path.display());
+            }
+            listed
+        }
+        None => Vec::new(),
+    };
+    if let Some(ref path) = args.exclude_file {
+        exclude.extend(NameMatcher::read_list(path, "exclude file")?);
+    }
+    for status in status_filters.iter().chain(&fail_on) {
+        if !KNOWN_STATUSES.contains(&status.as_str()) {
+            eprintln!(
+                "{}",
+                format!("Warning: '{}' is not a known dexopt status and may match nothing.", status).yellow().bold()
+            );
+        }
+    }
+
     if let Some(ref target) = args.optimize {
         let msg = if target == "all" {
             "Triggering background dexopt job...".to_string().bold()
@@ -492,29 +579,24 @@ fn main() -> Result<()> {
         println!("{} {}", prefix, msg);
 
         if target == "all" {
-            let status = Command::new("su")
-                .arg("-c")
-                .arg("cmd package bg-dexopt-job")
+            let status = shell
+                .privileged("cmd", &["package", "bg-dexopt-job"])
                 .status()
                 .with_context(|| "Failed to execute background optimization")?;
             if !status.success() {
                 eprintln!("{} Optimization command failed.", prefix);
             }
         } else {
-            let cmd1 = format!("pm art clear-app-profiles {}", target);
-            let status1 = Command::new("su")
-                .arg("-c")
-                .arg(&cmd1)
+            let status1 = shell
+                .privileged("pm", &["art", "clear-app-profiles", target])
                 .status()
                 .with_context(|| "Failed to clear app profiles")?;
             if !status1.success() {
                 eprintln!("{} Failed to clear app profiles for {}", prefix, target);
             }
 
-            let cmd2 = format!("cmd package compile -m speed -f {}", target);
-            let status2 = Command::new("su")
-                .arg("-c")
-                .arg(&cmd2)
+            let status2 = shell
+                .privileged("cmd", &["package", "compile", "-m", "speed", "-f", target])
                 .status()
                 .with_context(|| "Failed to compile package")?;
             if !status2.success() {
@@ -523,41 +605,279 @@ fn main() -> Result<()> {
         }
     }
 
-    if !args.json {
-        println!("{} {} ({}) ...", prefix, "Fetching package list".bold(), args.r#type);
+    let paths = args.path_filter.iter().map(|p| p.trim().to_string()).filter(|p| !p.is_empty()).collect();
+    let installers = args
+        .installer
+        .iter()
+        .map(|i| i.trim())
+        .filter(|i| !i.is_empty())
+        .map(|i| (!i.eq_ignore_ascii_case("none")).then(|| i.to_string()))
+        .collect();
+    let below_score = args.min_status.as_deref().map(UI::status_score);
+    let abi_gap = args.abi_filter.as_deref().map(Abi::from_isa);
+    let matchers =
+        Matchers { include, globs, listed, exclude, paths, installers, status: status_filters, reasons, below_score, abi_gap, fail_on };
+    let mut cache = match args.cache {
+        // Demo labels never come from (or go into) a real cache
+        Some(ref path) if !args.no_cache && !args.demo => Some(LabelCache::load(path)),
+        // Watch passes reuse labels resolved by earlier passes
+        _ if args.watch.is_some() => Some(LabelCache::in_memory()),
+        _ => None,
+    };
+
+    let policy_violated = match args.watch {
+        Some(seconds) => watch(&args, &shell, &matchers, &mut cache, seconds)?,
+        None => analyze(&args, &shell, &matchers, &mut cache)?,
+    };
+    if policy_violated {
+        std::process::exit(EXIT_POLICY_VIOLATION);
     }
-    let packages = Package::fetch_list(args.r#type)?;
 
-    if !args.json {
-        println!("{} Found {} packages.", prefix, packages.len().to_string().green().bold());
-        println!("{} {}", prefix, "Fetching dexopt dump...".bold());
+    Ok(())
+}
+
+/// Where the report goes: the `--output` file (truncated unless `append`) or stdout.
+fn open_output(path: Option<&Path>, append: bool) -> Result<Box<dyn Write>> {
+    let Some(path) = path else {
+        return Ok(Box::new(io::stdout()));
+    };
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .with_context(|| format!("Failed to open {} for writing", path.display()))?;
+    Ok(Box::new(io::BufWriter::new(file)))
+}
+
+/// Re-runs [`analyze`] every `seconds` until Ctrl-C, returning the `--fail-on`
+/// result of the last completed pass.
+fn watch(args: &Args, shell: &Shell, matchers: &Matchers, cache: &mut Option<LabelCache>, seconds: u64) -> Result<bool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&interrupted);
+    ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst)).context("Failed to install the Ctrl-C handler")?;
+
+    let prefix = "[-]".cyan();
+    let mut iterations = 0;
+    let mut policy_violated = false;
+    while !interrupted.load(Ordering::SeqCst) {
+        iterations += 1;
+        // Streamed records stay on a clean stdout; only the text view redraws
+        if args.format == Format::Text {
+            print!("\x1b[2J\x1b[H");
+            println!(
+                "{} {}",
+                prefix,
+                format!(
+                    "Every {}s, iteration {}, {}",
+                    seconds,
+                    iterations,
+                    humantime::format_rfc3339_seconds(SystemTime::now())
+                )
+                .bold()
+            );
+        }
+        match analyze(args, shell, matchers, cache) {
+            // Ctrl-C also reaches the device commands of the pass in flight
+            Err(_) if interrupted.load(Ordering::SeqCst) => {
+                iterations -= 1;
+                break;
+            }
+            result => policy_violated = result?,
+        }
+        // Sleep in short slices so Ctrl-C doesn't wait out the whole interval
+        let deadline = Instant::now() + Duration::from_secs(seconds);
+        while !interrupted.load(Ordering::SeqCst) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(100));
+        }
     }
-    let dump = Analyzer::fetch_dump()?;
-    let analyzer = Analyzer::new(&dump);
+    eprintln!("\n{} Stopped watching after {} iteration(s).", prefix, iterations);
+    Ok(policy_violated)
+}
 
-    if !args.json && !args.verbose {
-        UI::print_header();
+/// Records freshly resolved labels in the `--cache`, if one is in use.
+fn save_labels(cache: &mut Option<LabelCache>, resolved: Vec<(&Package, Option<String>)>, shell: &Shell) -> Result<()> {
+    let Some(cache) = cache else {
+        return Ok(());
+    };
+    if resolved.is_empty() {
+        return Ok(());
+    }
+    let mut stored = 0;
+    for (pkg, label) in resolved {
+        stored += usize::from(cache.insert(pkg, label, shell));
+    }
+    if stored == 0 {
+        eprintln!("{}", "Warning: couldn't read the size and time of any APK; no labels were cached.".yellow());
     }
+    cache.save()?;
+    Ok(())
+}
 
-    let mut stdout = io::stdout();
+/// One fetch-filter-render pass. Returns whether a displayed package matched
+/// `--fail-on`.
+fn analyze(args: &Args, shell: &Shell, matchers: &Matchers, cache: &mut Option<LabelCache>) -> Result<bool> {
+    let prefix = "[-]".cyan();
+    // Machine-readable output gets no progress chatter
+    let machine = args.format != Format::Text || args.print0;
+    // Formats with a record per package, which carry labels and artifact times
+    let records = !matches!(args.format, Format::Text | Format::Prometheus);
+    let chatty = !machine && !args.quiet;
+    let glyphs = Glyphs::select(args.ascii);
+
+    // With --output only the report goes to the file; progress stays on the terminal
+    let note = |msg: String| {
+        if args.output.is_some() {
+            eprintln!("{}", msg);
+        } else {
+            println!("{}", msg);
+        }
+    };
+    if chatty {
+        note(format!("{} {} ({}) ...", prefix, "Fetching package list".bold(), args.r#type));
+    }
+    let mut packages = if args.demo {
+        let mut list = Package::parse_list(demo::PACKAGES);
+        list.retain(|pkg| args.r#type == AppType::All || pkg.app_type == args.r#type);
+        for pkg in &mut list {
+            pkg.enabled = !demo::DISABLED.contains(&pkg.name.as_str());
+        }
+        list
+    } else {
+        Package::fetch_list(shell, args.r#type, args.user, args.package_file.as_deref())?
+    };
+    if let Some(ref name) = args.package {
+        packages.retain(|pkg| pkg.name == *name);
+    }
+
+    if chatty {
+        note(format!("{} Found {} packages.", prefix, packages.len().to_string().green().bold()));
+        note(format!("{} {}", prefix, "Fetching dexopt dump...".bold()));
+    }
+    let dump = if args.demo { demo::DEXOPT_DUMP.to_string() } else { Analyzer::fetch_dump(shell, args.dump_file.as_deref())? };
+    let mut analyzer = match args.package {
+        Some(ref name) => Analyzer::for_package(&dump, name),
+        None => Analyzer::new(&dump),
+    };
+    if let Some(ref name) = args.package
+        && packages.is_empty()
+    {
+        if analyzer.get_info(name).is_none() {
+            anyhow::bail!("Package '{}' not found in the package list or the dexopt dump", name);
+        }
+        // Known to the dump but not listed by pm (e.g. a stale --package-file)
+        packages.push(Package::new(name.as_str(), ""));
+    }
+    if let Some(level) = args.api_level {
+        for (status, count) in analyzer.apply_api_level(level) {
+            eprintln!(
+                "{}",
+                format!("Warning: status '{}' is unexpected on API level {} ({} entr{}).", status, level, count, if count == 1 { "y" } else { "ies" })
+                    .yellow()
+            );
+        }
+    }
+    // Artifact mtimes are only meaningful for a live dump of this device
+    if (args.verbose || records) && args.dump_file.is_none() && !args.adb && !args.demo {
+        analyzer.fill_artifact_times(&packages);
+    }
+    let baseline = args
+        .baseline
+        .as_deref()
+        .map(|path| Analyzer::fetch_dump(shell, Some(path)).map(|d| Analyzer::new(&d)))
+        .transpose()?;
+    // A saved dump may come from another device, so only a live one is
+    // compared against this device's properties
+    let defaults = args.compare_to_default.then(|| match args.dump_file {
+        Some(_) => DefaultFilters::aosp(),
+        None if args.demo => DefaultFilters::aosp(),
+        None => DefaultFilters::fetch(shell),
+    });
+
+    // Orphans are checked against every package, not just the --type scope
+    // (a --package-file capture is taken as complete)
+    let everything = if !args.reconcile || args.r#type == AppType::All || args.package_file.is_some() {
+        Vec::new()
+    } else if args.demo {
+        Package::parse_list(demo::PACKAGES)
+    } else {
+        Package::fetch_list(shell, AppType::All, args.user, None)?
+    };
+    let reconciliation = args
+        .reconcile
+        .then(|| Reconciliation::compute(&analyzer, &packages, if everything.is_empty() { &packages } else { &everything }));
+
+    // A single package may legitimately have no entries
+    let expected = if args.package.is_some() { 0 } else { packages.len() };
+    if analyzer.looks_truncated(&dump, expected) {
+        let source = match args.dump_file {
+            Some(ref path) => format!("The dump in {}", path.display()),
+            None => match current_uid(shell) {
+                Some(uid) => format!("The dexopt dump (running as {})", uid),
+                None => "The dexopt dump".to_string(),
+            },
+        };
+        eprintln!(
+            "{}",
+            format!(
+                "Warning: {} is empty or incomplete. dumpsys needs shell or root privileges to report dexopt state.",
+                source
+            )
+            .yellow()
+            .bold()
+        );
+    }
+
+    // The textfile collector may read at any time, so metrics replace the file in one step
+    let metrics_file = args.output.as_deref().filter(|_| args.format == Format::Prometheus);
+    let mut out = if metrics_file.is_some() { Box::new(io::sink()) } else { open_output(args.output.as_deref(), args.append)? };
     let mut stats: BTreeMap<String, usize> = BTreeMap::new();
+    let mut by_type: BTreeMap<AppType, TypeSummary> = BTreeMap::new();
+    let mut abi_coverage: BTreeMap<String, usize> = BTreeMap::new();
+    let mut reasons: BTreeMap<String, usize> = BTreeMap::new();
+    let mut abi_matrix: BTreeMap<String, BTreeMap<Abi, usize>> = BTreeMap::new();
     let mut total_displayed = 0;
+    let mut missing_data = 0;
+    let mut errors = 0;
+    let mut artifact_total = 0;
     let mut json_results = Vec::new();
 
-    // Step 1: name filter (cheap string match)
+    // Step 1: name, path and installer filters (cheap string match)
     let name_filtered: Vec<&Package> = packages
         .iter()
-        .filter(|pkg| args.filter.as_ref().map_or(true, |f| pkg.name.contains(f)))
+        .filter(|pkg| matchers.include.is_empty() || matchers.include.iter().any(|m| m.matches(&pkg.name)))
+        .filter(|pkg| matchers.globs.is_empty() || matchers.globs.iter().any(|m| m.matches(&pkg.name)))
+        .filter(|pkg| matchers.listed.is_empty() || matchers.listed.iter().any(|m| m.matches(&pkg.name)))
+        .filter(|pkg| !matchers.exclude.iter().any(|m| m.matches(&pkg.name)))
+        .filter(|pkg| matchers.paths.is_empty() || matchers.paths.iter().any(|p| pkg.path.contains(p.as_str())))
+        .filter(|pkg| matchers.installers.is_empty() || matchers.installers.contains(&pkg.installer))
+        .filter(|pkg| !args.updated_system || pkg.is_updated_system())
+        .filter(|pkg| !args.enabled_only || pkg.enabled)
         .collect();
 
     // Step 2: status filter (cheap lookup, no label fetching)
-    let status_filtered: Vec<(&Package, Option<&Vec<DexOptInfo>>)> = name_filtered
+    let mut status_filtered: Vec<(&Package, Option<&Vec<DexOptInfo>>)> = name_filtered
         .iter()
         .filter_map(|pkg| {
             let info_list = analyzer.get_info(&pkg.name);
-            if let Some(ref status_filter) = args.status {
+            if args.show_missing && info_list.is_some() {
+                return None;
+            }
+            if !matchers.status.is_empty() || !matchers.reasons.is_empty() || matchers.below_score.is_some() || matchers.abi_gap.is_some() {
                 let infos = info_list?;
-                if !infos.iter().any(|i| i.status.contains(status_filter)) {
+                if !matchers.status.is_empty() && !infos.iter().any(|i| matchers.status.contains(&i.status.to_lowercase())) {
+                    return None;
+                }
+                if !matchers.reasons.is_empty()
+                    && !infos.iter().any(|i| matchers.reasons.contains(&i.dimension_value(Dimension::Reason).to_lowercase()))
+                {
+                    return None;
+                }
+                if matchers.below_score.is_some_and(|limit| UI::best_score(infos) >= limit) {
+                    return None;
+                }
+                if matchers.abi_gap.is_some_and(|abi| !UI::lacks_optimization(infos, abi)) {
                     return None;
                 }
                 Some((*pkg, Some(infos)))
@@ -567,136 +887,445 @@ fn main() -> Result<()> {
         })
         .collect();
 
-    // Step 3: fetch labels only for survivors (parallel for verbose/json)
-    let display_data: Vec<(&Package, Option<String>, Option<&Vec<DexOptInfo>>)> =
-        if args.verbose || args.json {
-            status_filtered
-                .par_iter()
-                .map(|(pkg, info_list)| (*pkg, pkg.get_label(), *info_list))
-                .collect()
+    // Step 3: order the survivors; only a label sort has to wait for labels
+    let sort = if args.top.is_some() { SortBy::Status } else { args.sort };
+    if sort == SortBy::Status {
+        status_filtered.sort_by(|a, b| UI::package_score(a.1).cmp(&UI::package_score(b.1)).then_with(|| a.0.name.cmp(&b.0.name)));
+    }
+    let label_matches = |row: &DisplayRow| match args.label_filter {
+        Some(ref needle) => row.1.as_ref().is_some_and(|l| l.to_lowercase().contains(&needle.to_lowercase())),
+        None => true,
+    };
+    // --top and --limit both cap the output; without a label step the cap
+    // applies now, so labels are only resolved for packages that are shown
+    let cap = args.top.into_iter().chain(args.limit).min();
+    let mut matched = status_filtered.len();
+    if let Some(n) = cap
+        && args.label_filter.is_none()
+        && sort != SortBy::Label
+    {
+        status_filtered.truncate(n);
+    }
+
+    // Step 4: fetch labels only for survivors (parallel for verbose/machine formats,
+    // or for everything when filtering by label). The table sizes its package
+    // column to the longest label and the TUI and a label sort need them all,
+    // so only the other views print each package as soon as its label is in.
+    let resolve_labels =
+        args.label_filter.is_some() || args.tui || sort == SortBy::Label || ((args.verbose || records) && !args.quiet);
+    let table = args.format == Format::Text && !args.verbose;
+    let stream = resolve_labels && !args.tui && !table && sort != SortBy::Label;
+    let progress = UI::label_progress(status_filtered.len(), resolve_labels && !args.quiet && io::stderr().is_terminal());
+    let resolve = |pkg: &Package| -> Option<String> {
+        let label = cache
+            .as_ref()
+            .and_then(|c| c.lookup(pkg, shell))
+            .unwrap_or_else(|| if args.demo { demo::label(&pkg.name) } else { pkg.get_label(shell) });
+        progress.set_message(pkg.name.clone());
+        progress.inc(1);
+        label
+    };
+
+    let mut display_data: Vec<DisplayRow> = Vec::new();
+    let mut resolved: Vec<(&Package, Option<String>)> = Vec::new();
+    if !stream {
+        display_data = if resolve_labels {
+            status_filtered.par_iter().map(|(pkg, info_list)| (*pkg, resolve(pkg), *info_list)).collect()
         } else {
-            status_filtered
-                .iter()
-                .map(|(pkg, info_list)| (*pkg, None, *info_list))
-                .collect()
+            status_filtered.iter().map(|(pkg, info_list)| (*pkg, None, *info_list)).collect()
         };
+        progress.finish_and_clear();
+        if resolve_labels {
+            resolved = display_data.iter().map(|row| (row.0, row.1.clone())).collect();
+        }
+        display_data.retain(label_matches);
+        if args.label_filter.is_some() {
+            matched = display_data.len();
+        }
+        if sort == SortBy::Label {
+            display_data.sort_by(UI::compare_by_label);
+        }
+        if let Some(n) = cap {
+            display_data.truncate(n);
+        }
+    }
 
-    for (pkg, app_label, info_list) in display_data {
+    if args.tui {
+        if !io::stdout().is_terminal() {
+            anyhow::bail!("--tui needs an interactive terminal");
+        }
+        save_labels(cache, resolved, shell)?;
+        dexter::tui::run(&display_data, args.theme)?;
+        return Ok(false);
+    }
+
+    let longest_name = display_data
+        .iter()
+        .map(|row| UI::table_name(row.0, row.1.as_deref()).width())
+        .max()
+        .unwrap_or(0);
+    let pkg_width = UI::package_column_width(longest_name);
+
+    let grouped = (args.group_by.is_some() || baseline.is_some() || defaults.is_some()) && args.format == Format::Text;
+    if chatty && !args.verbose && !grouped {
+        UI::print_header(&mut out, pkg_width)?;
+    }
+
+    if args.format == Format::Csv {
+        writeln!(out, "package,label,abi,status,raw_line")?;
+    }
+
+    let mut emit = |row: &DisplayRow| -> Result<()> {
+        let &(pkg, ref app_label, info_list) = row;
         total_displayed += 1;
+        let artifact_bytes = args.show_sizes.then(|| pkg.artifact_size());
+        artifact_total += artifact_bytes.flatten().unwrap_or(0);
+        let type_summary = by_type.entry(pkg.app_type).or_default();
+        type_summary.total_apps += 1;
 
+        if info_list.is_none() {
+            missing_data += 1;
+        }
+        if UI::has_error(info_list) {
+            errors += 1;
+        }
         if let Some(infos) = info_list {
+            if let Some(key) = UI::abi_coverage(infos) {
+                *abi_coverage.entry(key).or_insert(0) += 1;
+            }
             for info in infos {
-                *stats.entry(info.status.clone()).or_insert(0) += 1;
+                let key = info.dimension_value(args.summary_by);
+                *stats.entry(key.to_string()).or_insert(0) += 1;
+                *type_summary.profiles.entry(key.to_string()).or_insert(0) += 1;
+                *reasons.entry(info.dimension_value(Dimension::Reason).to_string()).or_insert(0) += 1;
+                if args.count_abis {
+                    *abi_matrix.entry(info.status.clone()).or_default().entry(info.abi).or_insert(0) += 1;
+                }
             }
         }
 
-        if args.json {
-            json_results.push(json!({
+        // The HTML report is rendered from all rows at once below
+        if args.quiet || grouped || matches!(args.format, Format::Html | Format::Prometheus) {
+            return Ok(());
+        }
+
+        if args.print0 {
+            write!(out, "{}\0", pkg.name)?;
+        } else if matches!(args.format, Format::Json | Format::Yaml | Format::Toml | Format::Jsonl) {
+            let mut record = json!({
                 "package": pkg.name,
                 "label": app_label,
                 "path": pkg.path,
+                "installer": pkg.installer,
+                "enabled": pkg.enabled,
                 "dexopt_info": info_list
-            }));
+            });
+            if let Some(bytes) = artifact_bytes {
+                record["artifact_bytes"] = json!(bytes);
+            }
+            if args.raw {
+                record["raw_block"] = json!(analyzer.raw_block(&pkg.name));
+            }
+            if args.format == Format::Jsonl {
+                writeln!(out, "{}", serde_json::to_string(&record)?)?;
+                out.flush()?;
+            } else {
+                json_results.push(record);
+            }
+        } else if args.format == Format::Csv {
+            let label = app_label.as_deref().unwrap_or("");
+            for info in info_list.into_iter().flatten() {
+                let abi = match info.abi {
+                    Abi::Unknown => String::new(),
+                    abi => abi.to_string(),
+                };
+                writeln!(
+                    out,
+                    "{},{},{},{},{}",
+                    csv_escape(&pkg.name),
+                    csv_escape(label),
+                    csv_escape(&abi),
+                    csv_escape(if args.raw_status { &info.status_raw } else { &info.status }),
+                    csv_escape(&info.raw_line)
+                )?;
+            }
         } else if args.verbose {
-            UI::print_block_entry(&mut stdout, pkg, app_label.as_deref(), info_list)?;
+            UI::print_block_entry(&mut out, row, artifact_bytes, args.compiler_filter_recommendation, args.theme, glyphs)?;
         } else if let Some(infos) = info_list {
+            let name = UI::truncate_to_width(&UI::table_name(pkg, app_label.as_deref()), pkg_width);
             for (i, info) in infos.iter().enumerate() {
-                let colored_raw = UI::colorize_line(&info.raw_line, &info.status);
-                if i == 0 {
-                    writeln!(stdout, "{} | {}", format!("{:<45}", pkg.name).bright_white(), colored_raw)?;
-                } else {
-                    writeln!(stdout, "{:<45} | {}", "", colored_raw)?;
+                let mut colored_raw = UI::colorize_line(info.details(), &info.status, args.theme);
+                if args.compiler_filter_recommendation
+                    && let Some(filter) = UI::recommendation(pkg, info)
+                {
+                    colored_raw = format!("{} {}", colored_raw, UI::suggestion_note(filter));
                 }
+                let first = if i == 0 { name.bright_white().to_string() } else { String::new() };
+                let cells = [first, info.abi.to_string().dimmed().to_string(), colored_raw];
+                writeln!(out, "{}", UI::table_row(&cells, &[pkg_width, ABI_COLUMN_WIDTH]))?;
             }
-            writeln!(stdout)?;
+            print_size_row(&mut out, artifact_bytes, pkg_width)?;
+            writeln!(out)?;
         } else {
-            writeln!(
-                stdout,
-                "{} | {}",
-                format!("{:<45}", pkg.name).bright_white(),
-                "(no info found)".italic().red()
-            )?;
-            writeln!(stdout)?;
+            let name = UI::truncate_to_width(&UI::table_name(pkg, app_label.as_deref()), pkg_width);
+            let cells = [name.bright_white().to_string(), String::new(), "(no dexopt data)".italic().red().to_string()];
+            writeln!(out, "{}", UI::table_row(&cells, &[pkg_width, ABI_COLUMN_WIDTH]))?;
+            print_size_row(&mut out, artifact_bytes, pkg_width)?;
+            writeln!(out)?;
         }
-    }
-
-    if args.json {
-        println!("{}", serde_json::to_string_pretty(&json_results)?);
+        if args.raw && args.format == Format::Text {
+            UI::print_raw_block(&mut out, analyzer.raw_block(&pkg.name))?;
+        }
+        Ok(())
+    };
+
+    if stream {
+        // Workers finish out of order; rows are released in list order
+        thread::scope(|scope| -> Result<()> {
+            let (tx, rx) = mpsc::channel();
+            scope.spawn(|| {
+                status_filtered.par_iter().enumerate().for_each_with(tx, |tx, (i, (pkg, info_list))| {
+                    // The receiver only hangs up on an output error, reported below
+                    let _ = tx.send((i, (*pkg, resolve(pkg), *info_list)));
+                });
+            });
+            let mut ordered = OrderedBuffer::default();
+            let mut label_matched = 0;
+            for (i, row) in rx {
+                for row in ordered.push(i, row) {
+                    resolved.push((row.0, row.1.clone()));
+                    if !label_matches(&row) {
+                        continue;
+                    }
+                    label_matched += 1;
+                    if cap.is_some_and(|n| display_data.len() >= n) {
+                        continue;
+                    }
+                    progress.suspend(|| emit(&row))?;
+                    display_data.push(row);
+                }
+            }
+            if args.label_filter.is_some() {
+                matched = label_matched;
+            }
+            Ok(())
+        })?;
+        progress.finish_and_clear();
     } else {
-        UI::print_summary(total_displayed, &stats, args.r#type);
-
-        if args.verbose && !Package::is_aapt_available() {
-            println!();
-            eprintln!("{}", "Warning: 'aapt' is not installed. Some application labels might be missing.".yellow().bold());
-            eprintln!("{}", "Install it via 'pkg install aapt' for the best experience.".yellow().bold());
+        for row in &display_data {
+            emit(row)?;
         }
     }
+    save_labels(cache, resolved, shell)?;
+    if args.limit.is_some() && matched > display_data.len() && !args.quiet {
+        eprintln!(
+            "{}",
+            format!("Note: output limited to the first {} of {} matching packages (--limit).", display_data.len(), matched).yellow()
+        );
+    }
+    let missing_artifacts = args.verify_artifacts.then(|| MissingArtifact::find(&display_data, Path::new(DALVIK_CACHE)));
+
+    let summary_json = (matches!(args.format, Format::Json | Format::Yaml | Format::Toml) || args.summary_json.is_some()).then(|| {
+        let mut summary = json!({
+            "app_type": args.r#type.to_string(),
+            "total_apps": total_displayed,
+            "missing_data": missing_data,
+            "errors": errors,
+            "group_by": args.summary_by,
+            "profiles": stats,
+            "abi_coverage": abi_coverage,
+            "reasons": reasons,
+        });
+        if args.count_abis {
+            summary["abi_matrix"] = json!(abi_matrix);
+        }
+        if args.r#type == AppType::All {
+            summary["by_app_type"] = json!(by_type);
+        }
+        if args.show_sizes {
+            summary["artifact_bytes"] = json!(artifact_total);
+        }
+        if let Some(level) = args.api_level {
+            summary["api_level"] = json!(level);
+        }
+        summary
+    });
+    if let Some(ref path) = args.summary_json
+        && let Some(ref summary) = summary_json
+    {
+        write_atomic(path, &format!("{}\n", serde_json::to_string_pretty(summary)?))
+            .with_context(|| format!("Failed to write the summary to {}", path.display()))?;
+    }
 
-    Ok(())
-}
+    if matches!(args.format, Format::Json | Format::Yaml | Format::Toml)
+        && let Some(summary) = summary_json
+    {
+        let mut report = if args.quiet {
+            summary
+        } else {
+            json!({ "packages": json_results, "summary": summary })
+        };
+        if let Some(ref defaults) = defaults
+            && !args.quiet
+        {
+            report["deviations"] = json!(defaults.deviations(&display_data));
+        }
+        if let Some(ref baseline) = baseline
+            && !args.quiet
+        {
+            report["baseline_diff"] = json!(StatusDiff::compute(&display_data, baseline));
+        }
+        if let Some(ref rec) = reconciliation
+            && !args.quiet
+        {
+            report["reconciliation"] = json!(rec);
+        }
+        if let Some(ref missing) = missing_artifacts
+            && !args.quiet
+        {
+            report["missing_artifacts"] = json!(missing);
+        }
+        // All serialize the same sorted-key document, so runs diff cleanly
+        match args.format {
+            Format::Yaml => write!(out, "{}", serde_yaml::to_string(&report)?)?,
+            Format::Toml => {
+                // TOML documents are tables: the summary gets its own, and
+                // each package becomes a [[package]] entry
+                let mut report = if args.quiet { json!({ "summary": report }) } else { report };
+                if let Some(packages) = report.as_object_mut().and_then(|r| r.remove("packages")) {
+                    report["package"] = packages;
+                }
+                write!(out, "{}", toml_string(&report)?)?;
+            }
+            _ => writeln!(out, "{}", serde_json::to_string_pretty(&report)?)?,
+        }
+    } else if args.format == Format::Html {
+        let report = html::render(&display_data, &stats, args.r#type, args.summary_by, args.sort_summary, args.theme, args.quiet);
+        write!(out, "{}", report)?;
+    } else if args.format == Format::Prometheus {
+        let metrics = prometheus::render(&display_data, &stats, args.summary_by);
+        match metrics_file {
+            Some(path) => write_atomic(path, &metrics).with_context(|| format!("Failed to write metrics to {}", path.display()))?,
+            None => write!(out, "{}", metrics)?,
+        }
+    } else if args.format == Format::Text && !args.print0 {
+        if let Some(dim) = args.group_by
+            && !args.quiet
+        {
+            UI::print_groups(&mut out, &display_data, dim, args.theme)?;
+        }
+        if let Some(ref baseline) = baseline
+            && !args.quiet
+        {
+            UI::print_diff(&mut out, &StatusDiff::compute(&display_data, baseline))?;
+        }
+        if let Some(ref defaults) = defaults
+            && !args.quiet
+        {
+            UI::print_deviations(&mut out, &defaults.deviations(&display_data), defaults.from_device, args.theme)?;
+        }
+        if let Some(ref rec) = reconciliation
+            && !args.quiet
+        {
+            UI::print_reconciliation(&mut out, rec)?;
+        }
+        if let Some(ref missing) = missing_artifacts
+            && !args.quiet
+        {
+            UI::print_missing_artifacts(&mut out, missing, args.theme)?;
+        }
+        let summary = Summary {
+            total_apps: total_displayed,
+            missing_data,
+            errors,
+            stats: &stats,
+            app_type: args.r#type,
+            summary_by: args.summary_by,
+            by_type: &by_type,
+            artifact_bytes: args.show_sizes.then_some(artifact_total),
+            abi_coverage: &abi_coverage,
+            reasons: &reasons,
+            order: args.sort_summary,
+        };
+        UI::print_summary(&mut out, &summary, args.theme, glyphs)?;
+        if args.count_abis {
+            UI::print_abi_matrix(&mut out, &abi_matrix, args.sort_summary, args.theme)?;
+        }
+        if !args.quiet {
+            UI::print_errors(&mut out, &display_data)?;
+            UI::print_optimizable(&mut out, &display_data, args.theme)?;
+        }
+        if args.explain {
+            UI::print_legend(&mut out, &display_data, args.theme)?;
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_analyzer_parsing() {
-        let sample_dump = r#"
-[com.example.app]
-  arm64: [status=speed-profile] [reason=bg-dexopt] [primary-abi]
-[com.system.service]
-  arm64: [status=verify] [reason=prebuilt]
-"#;
-        let analyzer = Analyzer::new(sample_dump);
-
-        let info_app = analyzer.get_info("com.example.app").unwrap();
-        assert_eq!(info_app.len(), 1);
-        assert_eq!(info_app[0].status, "speed-profile");
-
-        let info_sys = analyzer.get_info("com.system.service").unwrap();
-        assert_eq!(info_sys.len(), 1);
-        assert_eq!(info_sys[0].status, "verify");
-
-        assert!(analyzer.get_info("non.existent").is_none());
-    }
-
-    #[test]
-    fn test_label_heuristic_filters_class_names() {
-        let cases: &[(&str, bool)] = &[
-            ("com.example.SomeActivity", false),                        // class-like
-            ("com.foo.bar", false),                                     // package-like
-            ("@0x1040001", false),                                      // resource ref
-            ("https://www.facebook.com/.well-known/assetlinks.json", false), // URL
-            ("[{ \"include\": \"https://example.com\" }]", false), // JSON blob
-            ("{ \"key\": \"value\" }", false),                     // JSON object
-            ("a".repeat(65).as_str(), false),                           // too long
-            ("My Cool App", true),                                      // real label
-            ("MyApp", true),                                            // simple word
-            ("Calculator", true),                                       // simple word
-            ("Facebook", true),                                         // real label
-        ];
-
-        for (label, should_keep) in cases {
-            assert_eq!(
-                Package::is_valid_label(label),
-                *should_keep,
-                "Failed for label: {:?}", label
+        if args.verbose && !args.demo && !Package::is_aapt_available(shell) {
+            eprintln!();
+            let missing = match args.aapt_path {
+                Some(ref path) => format!("'{}' is not installed", path),
+                None => "Neither 'aapt2' nor 'aapt' is installed".to_string(),
+            };
+            eprintln!("{}", format!("Warning: {}. Some application labels might be missing.", missing).yellow().bold());
+            eprintln!(
+                "{}",
+                "Install it via 'pkg install aapt2' (or aapt), or pass --aapt-path, for the best experience.".yellow().bold()
             );
         }
     }
 
-    #[test]
-    fn test_box_padding_is_exact() {
-        // p_l + content_width + p_r must equal box_width exactly
-        for content_len in [5, 20, 40, 60, 80] {
-            let min_width: usize = 40;
-            let max_term_width: usize = 120;
-            let box_width = (content_len + 4).max(min_width).min(max_term_width);
-            let p_space = box_width.saturating_sub(content_len);
-            let p_l = p_space / 2;
-            let p_r = p_space - p_l;
-            assert_eq!(p_l + content_len + p_r, box_width,
-                "Padding mismatch for content_len={}", content_len);
+    if let Some(ref filter) = args.compile {
+        let target_score = UI::status_score(filter);
+        let (pending, optimized): (Vec<&DisplayRow>, Vec<&DisplayRow>) = display_data
+            .iter()
+            .partition(|row| UI::package_score(row.2) < target_score);
+
+        // Keep machine-readable output clean
+        let mut stderr = io::stderr();
+        let out: &mut dyn Write = if machine { &mut stderr } else { &mut *out };
+
+        if args.dry_run {
+            UI::print_compile_plan(out, filter, &pending, optimized.len(), args.theme)?;
+        } else {
+            let mut results: Vec<(&str, CompileOutcome)> = optimized
+                .iter()
+                .map(|row| (row.0.name.as_str(), CompileOutcome::AlreadyOptimized))
+                .collect();
+
+            if !pending.is_empty() {
+                let prompt = format!("Compile {} package(s) with '{}'?", pending.len(), filter);
+                if !args.yes && !confirm(&prompt)? {
+                    eprintln!("{} Compilation cancelled.", prefix);
+                    return Ok(false);
+                }
+                // Finish the compiles in flight but start no new ones
+                let interrupted = Arc::new(AtomicBool::new(false));
+                let flag = Arc::clone(&interrupted);
+                ctrlc::set_handler(move || flag.store(true, Ordering::SeqCst))
+                    .context("Failed to install the Ctrl-C handler")?;
+
+                let names: Vec<&str> = pending.iter().map(|row| row.0.name.as_str()).collect();
+                let progress = UI::compile_progress(names.len(), !args.quiet && io::stderr().is_terminal());
+                results.extend(compile_all(shell, &names, filter, &interrupted, |name| {
+                    progress.set_message(name.to_string());
+                    progress.inc(1);
+                }));
+                progress.finish_and_clear();
+                if interrupted.load(Ordering::SeqCst) {
+                    eprintln!("{} Interrupted; remaining compiles were not started.", prefix);
+                }
+            }
+
+            results.sort_by(|a, b| a.0.cmp(b.0));
+            UI::print_compile_results(out, filter, &results)?;
         }
     }
+
+    if args.stats_line {
+        writeln!(out, "{}", stats_line(total_displayed, &stats))?;
+    }
+    out.flush()?;
+    Ok(display_data.iter().any(|row| {
+        row.2
+            .is_some_and(|infos| infos.iter().any(|i| matchers.fail_on.contains(&i.status.to_lowercase())))
+    }))
 }
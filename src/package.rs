@@ -0,0 +1,927 @@
+//! Installed packages, label resolution and on-device recompilation.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use apk_info::Apk;
+use clap::ValueEnum;
+use colored::*;
+use globset::{Glob, GlobMatcher};
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::Abi;
+use crate::error::DexoptError;
+use crate::shell::{decode_lossy, Shell};
+
+/// Which installed packages to analyze. Individual packages are classified
+/// as either `User` or `System`.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppType {
+    User,
+    System,
+    All,
+}
+
+impl fmt::Display for AppType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AppType::User => "User",
+            AppType::System => "System",
+            AppType::All => "All",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where ART keeps compiled artifacts for APKs whose own directory isn't
+/// writable (system images).
+pub const DALVIK_CACHE: &str = "/data/dalvik-cache";
+
+/// An installed package as reported by `pm list packages -f -i`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Package {
+    pub name: String,
+    pub path: String,
+    /// Whether this is a user-installed or a system package.
+    pub app_type: AppType,
+    /// The package that installed this one (e.g. `com.android.vending`), or
+    /// `None` when pm has no installer on record (adb installs, system apps,
+    /// captures listed without `-i`).
+    pub installer: Option<String>,
+    /// False for packages `pm list packages -d` reports as disabled. Saved
+    /// captures carry no state, so their packages count as enabled.
+    pub enabled: bool,
+}
+
+impl Package {
+    /// Creates a package, classifying it by install location: system images
+    /// live outside `/data`. Updated system apps are refined by `fetch_list`.
+    pub fn new(name: impl Into<String>, path: impl Into<String>) -> Self {
+        let path = path.into();
+        let app_type = if path.starts_with("/data/") { AppType::User } else { AppType::System };
+        Package { name: name.into(), path, app_type, installer: None, enabled: true }
+    }
+
+    /// A system app whose update was installed over the image copy: still a
+    /// system package to `pm`, but its code now lives under `/data/app`.
+    pub fn is_updated_system(&self) -> bool {
+        self.app_type == AppType::System && self.path.starts_with("/data/app/")
+    }
+
+    /// Fetches the package list using `pm list packages` (for `user`, or the
+    /// current user), or reads it from `source` when a saved capture is given.
+    pub fn fetch_list(shell: &Shell, app_type: AppType, user: Option<u32>, source: Option<&Path>) -> Result<Vec<Self>, DexoptError> {
+        if let Some(path) = source {
+            let raw = std::fs::read(path).map_err(|source| DexoptError::FileRead {
+                what: "package list",
+                path: path.to_path_buf(),
+                source,
+            })?;
+            return Ok(Self::parse_list(&decode_lossy(&raw, &format!("The package list in {}", path.display()))));
+        }
+
+        let pm_args = Self::list_args(app_type, user, &["-f", "-i"]);
+        let mut cmd = shell.command("pm", &pm_args.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let output = shell.checked_output("pm list packages", "pm", &mut cmd)?;
+        let mut list = Self::parse_list(&decode_lossy(&output.stdout, "The pm package list"));
+
+        match app_type {
+            AppType::User | AppType::System => {
+                for pkg in &mut list {
+                    pkg.app_type = app_type;
+                }
+            }
+            // The path heuristic misses updated system apps under /data/app,
+            // so ask pm which packages are system ones
+            AppType::All => {
+                let system_args = Self::list_args(AppType::System, user, &[]);
+                let mut cmd = shell.command("pm", &system_args.iter().map(String::as_str).collect::<Vec<_>>());
+                if let Ok(output) = shell.output(&mut cmd)
+                    && output.status.success()
+                {
+                    let raw = String::from_utf8_lossy(&output.stdout);
+                    let system = Self::listed_names(&raw);
+                    for pkg in &mut list {
+                        pkg.app_type = if system.contains(pkg.name.as_str()) { AppType::System } else { AppType::User };
+                    }
+                }
+            }
+        }
+
+        let disabled_args = Self::list_args(app_type, user, &["-d"]);
+        let mut cmd = shell.command("pm", &disabled_args.iter().map(String::as_str).collect::<Vec<_>>());
+        if let Ok(output) = shell.output(&mut cmd)
+            && output.status.success()
+        {
+            let raw = String::from_utf8_lossy(&output.stdout);
+            let disabled = Self::listed_names(&raw);
+            for pkg in &mut list {
+                pkg.enabled = !disabled.contains(pkg.name.as_str());
+            }
+        }
+        Ok(list)
+    }
+
+    /// Package names in `pm list packages` output without `-f` (`package:<name>` lines).
+    fn listed_names(raw: &str) -> HashSet<&str> {
+        raw.lines().filter_map(|l| l.trim().strip_prefix("package:")).map(str::trim).collect()
+    }
+
+    /// Arguments for `pm list packages` restricted to `app_type` and `user`.
+    fn list_args(app_type: AppType, user: Option<u32>, extra: &[&str]) -> Vec<String> {
+        let mut args: Vec<String> = ["list", "packages"].iter().chain(extra).map(|a| a.to_string()).collect();
+        match app_type {
+            AppType::User => args.push("-3".to_string()),
+            AppType::System => args.push("-s".to_string()),
+            AppType::All => {}
+        }
+        if let Some(user) = user {
+            args.extend(["--user".to_string(), user.to_string()]);
+        }
+        args
+    }
+
+    /// Parses `pm list packages -f` output (`package:<path>=<name>` lines,
+    /// with a trailing `installer=<package>` when listed with `-i`).
+    pub fn parse_list(raw: &str) -> Vec<Self> {
+        let mut list = Vec::new();
+
+        for line in raw.lines() {
+            let Some(p) = line.trim().strip_prefix("package:") else {
+                continue;
+            };
+            // Split the installer off first: the path itself may contain '='
+            let (p, installer) = match p.split_once(" installer=") {
+                Some((p, installer)) => (p, Some(installer.trim())),
+                None => (p, None),
+            };
+            // A name garbled by invalid UTF-8 would match nothing on the device
+            if let Some((path, name)) = p.rsplit_once('=')
+                && !name.contains(char::REPLACEMENT_CHARACTER)
+            {
+                let mut pkg = Package::new(name.trim(), path.trim());
+                pkg.installer = installer.filter(|i| !i.is_empty() && *i != "null").map(str::to_string);
+                list.push(pkg);
+            }
+        }
+
+        // Some ROMs list every split of a package separately; keep one entry
+        // per package, preferring the base APK
+        list.sort_by(|a, b| {
+            a.name
+                .cmp(&b.name)
+                .then_with(|| Self::is_split_path(&a.path).cmp(&Self::is_split_path(&b.path)))
+        });
+        list.dedup_by(|later, first| later.name == first.name);
+        list
+    }
+
+    fn is_split_path(path: &str) -> bool {
+        Path::new(path)
+            .file_name()
+            .is_some_and(|f| f.to_string_lossy().starts_with("split_"))
+    }
+
+    /// Returns the base APK for this package. `pm` may report the install
+    /// directory or a split APK, neither of which carries the application label.
+    pub fn base_apk(&self) -> PathBuf {
+        let path = Path::new(&self.path);
+        if path.is_dir() {
+            path.join("base.apk")
+        } else if Self::is_split_path(&self.path) {
+            path.with_file_name("base.apk")
+        } else {
+            path.to_path_buf()
+        }
+    }
+
+    /// Modification time of the compiled oat (or vdex) artifact for `abi`,
+    /// which ART keeps in `oat/<isa>/` next to the APK.
+    pub fn artifact_mtime(&self, abi: Abi) -> Option<SystemTime> {
+        let isa = abi.isa()?;
+        let apk = self.base_apk();
+        let stem = apk.file_stem()?.to_string_lossy().into_owned();
+        let oat_dir = apk.parent()?.join("oat").join(isa);
+        ["odex", "vdex"]
+            .iter()
+            .find_map(|ext| std::fs::metadata(oat_dir.join(format!("{}.{}", stem, ext))).ok()?.modified().ok())
+    }
+
+    /// Whether compiled code for `abi` is on disk: `oat/<isa>/<stem>.odex`
+    /// next to the APK, or the APK's entry in `dalvik_cache` (where ART puts
+    /// artifacts for read-only system images), `<isa>/<path with '/' as '@'>@classes.dex`.
+    /// `None` for an unknown ABI.
+    pub fn has_compiled_artifact(&self, abi: Abi, dalvik_cache: &Path) -> Option<bool> {
+        let isa = abi.isa()?;
+        let apk = self.base_apk();
+        let stem = apk.file_stem()?.to_string_lossy().into_owned();
+        let next_to_apk = apk.parent()?.join("oat").join(isa).join(format!("{}.odex", stem));
+        let cache_name = format!("{}@classes.dex", apk.to_string_lossy().trim_start_matches('/').replace('/', "@"));
+        Some(next_to_apk.is_file() || dalvik_cache.join(isa).join(cache_name).is_file())
+    }
+
+    /// Combined size of the compiled artifacts ([`ARTIFACT_EXTENSIONS`]) in
+    /// every `oat/<isa>/` directory next to the APK, or `None` when there is
+    /// no oat directory (e.g. artifacts live under `/data/dalvik-cache`).
+    pub fn artifact_size(&self) -> Option<u64> {
+        let oat_dir = self.base_apk().parent()?.join("oat");
+        let mut total = 0;
+        for isa_dir in std::fs::read_dir(oat_dir).ok()?.flatten() {
+            let Ok(files) = std::fs::read_dir(isa_dir.path()) else {
+                continue;
+            };
+            for file in files.flatten() {
+                let path = file.path();
+                if path.extension().is_some_and(|ext| ARTIFACT_EXTENSIONS.iter().any(|a| ext == *a))
+                    && let Ok(meta) = file.metadata()
+                    && meta.is_file()
+                {
+                    total += meta.len();
+                }
+            }
+        }
+        Some(total)
+    }
+
+    /// Identifies the current APK contents by size and modification time, so
+    /// cached labels are re-resolved after an update. Over ADB the APK is on
+    /// the device, so both come from `stat` there.
+    pub fn fingerprint(&self, shell: &Shell) -> Option<(u64, u64)> {
+        if shell.is_remote() {
+            let apk = self.base_apk();
+            let output = shell.output(&mut shell.command("stat", &["-c", "%s %Y", &apk.to_string_lossy()])).ok()?;
+            return output.status.success().then(|| parse_stat(&String::from_utf8_lossy(&output.stdout))).flatten();
+        }
+        let meta = std::fs::metadata(self.base_apk()).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(std::time::UNIX_EPOCH).ok()?;
+        Some((meta.len(), mtime.as_secs()))
+    }
+
+    /// Gets the application label from the APK file. A value either source
+    /// reports that [`clean_label`] rejects (e.g. `@string/app_name`) counts
+    /// as unresolved, so the other source still gets a chance.
+    pub fn get_label(&self, shell: &Shell) -> Option<String> {
+        // 1. aapt: resolves string resources directly from the APK — most accurate
+        if let Some(label) = self.get_label_from_aapt(shell).as_deref().and_then(clean_label) {
+            return Some(label);
+        }
+
+        // 2. apk-info native parsing: fast fallback when aapt is unavailable
+        self.read_apk_label().ok().flatten()
+    }
+
+    /// Reads the application label natively from the base APK, returning
+    /// `None` when the APK has no usable label.
+    pub fn read_apk_label(&self) -> Result<Option<String>, DexoptError> {
+        let path = self.base_apk();
+        let apk = Apk::new(&path).map_err(|e| DexoptError::ApkRead { path: path.clone(), reason: e.to_string() })?;
+        Ok(apk.get_application_label().as_deref().and_then(clean_label))
+    }
+
+    /// Returns true if the string looks like a real human-readable app label.
+    /// Rejects resource refs, class names, URLs, JSON blobs, and other garbage
+    /// that some APKs mistakenly store in the application-label field.
+    pub fn is_valid_label(label: &str) -> bool {
+        // Unresolved resource reference e.g. "@0x1040001" or "@string/app_name"
+        if label.starts_with('@') {
+            return false;
+        }
+        // URL e.g. "https://www.facebook.com/.well-known/..."
+        if label.starts_with("http://") || label.starts_with("https://") {
+            return false;
+        }
+        // JSON blob e.g. "[{ \"include\": ... }]" or "{ ... }"
+        if label.starts_with('[') || label.starts_with('{') {
+            return false;
+        }
+        if Self::is_class_like(label) {
+            return false;
+        }
+        // Suspiciously long strings are almost certainly not a real label
+        if label.len() > 64 {
+            return false;
+        }
+        true
+    }
+
+    /// A bare package or class name such as "com.facebook.katana" or
+    /// "com.foo.MainActivity": dotted Java identifiers whose first segment is
+    /// lowercase. Brand names like "Booking.com" or "1.1.1.1" don't qualify.
+    fn is_class_like(label: &str) -> bool {
+        let is_identifier = |segment: &str| {
+            let mut chars = segment.chars();
+            chars.next().is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+                && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+        };
+        let mut segments = label.split('.');
+        let first = segments.next().unwrap_or_default();
+        label.contains('.')
+            && is_identifier(first)
+            && !first.chars().any(char::is_uppercase)
+            && segments.all(is_identifier)
+    }
+
+    /// Runs `aapt dump badging` on the base APK. The `aapt` program may be
+    /// mapped to `aapt2` (see [`Package::find_aapt`]), which accepts the same
+    /// invocation and prints the same `application-label:` line.
+    pub fn get_label_from_aapt(&self, shell: &Shell) -> Option<String> {
+        let apk = self.base_apk();
+        let mut cmd = shell.command("aapt", &["dump", "badging", &apk.to_string_lossy()]);
+        let output = match shell.output(&mut cmd) {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                eprintln!("{}", format!("Warning: aapt {} for {}, skipping its label.", e, self.name).yellow());
+                return None;
+            }
+            Err(_) => return None,
+        };
+
+        if !output.status.success() {
+            return None;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        for line in stdout.lines() {
+            let trimmed = line.trim();
+            if let Some(label) = trimmed.strip_prefix("application-label:'")
+                && let Some(end) = label.find('\'')
+            {
+                return Some(label[..end].to_string());
+            }
+        }
+        None
+    }
+
+    /// Whether the binary `aapt` resolves to exists on the target.
+    pub fn is_aapt_available(shell: &Shell) -> bool {
+        Self::is_installed(shell, shell.program("aapt"))
+    }
+
+    /// The first of [`AAPT_TOOLS`] installed on the target.
+    pub fn find_aapt(shell: &Shell) -> Option<&'static str> {
+        AAPT_TOOLS.iter().copied().find(|tool| Self::is_installed(shell, tool))
+    }
+
+    fn is_installed(shell: &Shell, program: &str) -> bool {
+        shell.which(program).is_some()
+    }
+}
+
+/// Files ART writes for a compiled APK: code, verified dex, and the app image.
+pub const ARTIFACT_EXTENSIONS: &[&str] = &["odex", "vdex", "art"];
+
+/// Tools that can print an APK's label with `dump badging`, most preferred
+/// first: current SDK and Termux installs only ship `aapt2`.
+pub const AAPT_TOOLS: &[&str] = &["aapt2", "aapt"];
+
+/// Normalizes a raw application-label value (trimmed, line breaks folded
+/// into spaces) and returns it if [`Package::is_valid_label`] accepts it.
+/// `None` means "unresolved": the caller should try the next label source.
+pub fn clean_label(raw: &str) -> Option<String> {
+    let label = raw.trim().replace(['\r', '\n'], " ");
+    (!label.is_empty() && Package::is_valid_label(&label)).then_some(label)
+}
+
+/// Size and modification time from `stat -c '%s %Y'` output.
+fn parse_stat(raw: &str) -> Option<(u64, u64)> {
+    let mut fields = raw.split_whitespace().map(str::parse);
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(Ok(size)), Some(Ok(mtime)), None) => Some((size, mtime)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedLabel {
+    label: Option<String>,
+    size: u64,
+    mtime: u64,
+}
+
+/// On-disk map of package name to resolved label. Lookups happen from the
+/// parallel resolver; updates are written once at the end of the run.
+pub struct LabelCache {
+    /// `None` for a cache that only lives as long as the process.
+    path: Option<PathBuf>,
+    entries: BTreeMap<String, CachedLabel>,
+    /// In-memory labels by package name and APK path. A process doesn't
+    /// outlive an update going unnoticed, and no APK is read to check.
+    session: HashMap<(String, String), Option<String>>,
+    /// Fingerprints [`LabelCache::lookup`] read this run, by APK path, so
+    /// `insert` doesn't `stat` each APK again (a round trip over ADB).
+    fingerprints: Mutex<HashMap<String, (u64, u64)>>,
+}
+
+impl LabelCache {
+    /// Loads the cache, starting empty when the file is missing or unreadable.
+    pub fn load(path: &Path) -> Self {
+        let entries = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|raw| serde_json::from_str(&raw).ok())
+            .unwrap_or_default();
+        LabelCache { path: Some(path.to_path_buf()), entries, session: HashMap::new(), fingerprints: Mutex::default() }
+    }
+
+    /// An empty cache that is never written to disk, for reuse within one process.
+    pub fn in_memory() -> Self {
+        LabelCache { path: None, entries: BTreeMap::new(), session: HashMap::new(), fingerprints: Mutex::default() }
+    }
+
+    /// Returns the cached label if the APK hasn't changed since it was stored.
+    pub fn lookup(&self, pkg: &Package, shell: &Shell) -> Option<Option<String>> {
+        if self.path.is_none() {
+            return self.session.get(&(pkg.name.clone(), pkg.path.clone())).cloned();
+        }
+        let (size, mtime) = pkg.fingerprint(shell)?;
+        self.fingerprints.lock().unwrap_or_else(|e| e.into_inner()).insert(pkg.path.clone(), (size, mtime));
+        self.entries
+            .get(&pkg.name)
+            .filter(|c| c.size == size && c.mtime == mtime)
+            .map(|c| c.label.clone())
+    }
+
+    /// Stores `label`; returns false when the APK's fingerprint couldn't be read.
+    pub fn insert(&mut self, pkg: &Package, label: Option<String>, shell: &Shell) -> bool {
+        if self.path.is_none() {
+            self.session.insert((pkg.name.clone(), pkg.path.clone()), label);
+            return true;
+        }
+        let known = self.fingerprints.get_mut().unwrap_or_else(|e| e.into_inner()).get(&pkg.path).copied();
+        let Some((size, mtime)) = known.or_else(|| pkg.fingerprint(shell)) else {
+            return false;
+        };
+        self.entries.insert(pkg.name.clone(), CachedLabel { label, size, mtime });
+        true
+    }
+
+    /// Writes the cache via a temporary file so an interrupted run can't leave it truncated.
+    pub fn save(&self) -> Result<(), DexoptError> {
+        let Some(ref path) = self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(&self.entries)
+            .map_err(|source| DexoptError::Serialize { what: "label cache", source })?;
+        write_atomic(path, &json).map_err(|source| DexoptError::FileWrite { what: "label cache", path: path.clone(), source })
+    }
+}
+
+/// Replaces `path` with `contents` through a temporary file in the same
+/// directory, so readers see either the old file or the new one. The
+/// temporary name carries the pid, so concurrent runs don't share it, and
+/// it is removed again if either step fails.
+pub fn write_atomic(path: &Path, contents: &str) -> io::Result<()> {
+    let name = path.file_name().map_or_else(|| "dexter".into(), |n| n.to_string_lossy().into_owned());
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+    std::fs::write(&tmp, contents).and_then(|()| std::fs::rename(&tmp, path)).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+/// How name patterns are matched against package names.
+#[derive(Debug)]
+pub enum NameMatcher {
+    Substring(String),
+    Regex(Regex),
+    Glob(GlobMatcher),
+}
+
+impl NameMatcher {
+    pub fn new(pattern: &str, regex: bool) -> Result<Self, DexoptError> {
+        if regex {
+            let re = Regex::new(pattern).map_err(|e| DexoptError::ParseError {
+                what: "regex",
+                input: pattern.to_string(),
+                reason: e.to_string(),
+            })?;
+            Ok(NameMatcher::Regex(re))
+        } else {
+            Ok(NameMatcher::Substring(pattern.to_string()))
+        }
+    }
+
+    /// Shell-style pattern matched against the whole name, e.g. `com.google.*`.
+    pub fn glob(pattern: &str) -> Result<Self, DexoptError> {
+        let glob = Glob::new(pattern).map_err(|e| DexoptError::ParseError {
+            what: "glob",
+            input: pattern.to_string(),
+            reason: e.kind().to_string(),
+        })?;
+        Ok(NameMatcher::Glob(glob.compile_matcher()))
+    }
+
+    /// Parses a package list file: one exact name or glob per line, with
+    /// blank lines and lines starting with `#` skipped.
+    pub fn parse_list(raw: &str) -> Result<Vec<Self>, DexoptError> {
+        raw.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Self::glob)
+            .collect()
+    }
+
+    /// Reads `path` with [`NameMatcher::parse_list`]; `what` names the file in errors.
+    pub fn read_list(path: &Path, what: &'static str) -> Result<Vec<Self>, DexoptError> {
+        let raw = std::fs::read_to_string(path).map_err(|source| DexoptError::FileRead { what, path: path.to_path_buf(), source })?;
+        Self::parse_list(&raw)
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NameMatcher::Substring(s) => name.contains(s.as_str()),
+            NameMatcher::Regex(re) => re.is_match(name),
+            NameMatcher::Glob(glob) => glob.is_match(name),
+        }
+    }
+}
+
+/// Outcome of recompiling a single package.
+#[derive(Debug)]
+pub enum CompileOutcome {
+    Compiled,
+    AlreadyOptimized,
+    Failed(String),
+    /// Never started because the batch was interrupted.
+    Cancelled,
+}
+
+/// The command line that recompiles `package` with `filter`.
+pub fn compile_command(package: &str, filter: &str) -> String {
+    format!("cmd {}", compile_args(package, filter).join(" "))
+}
+
+fn compile_args<'a>(package: &'a str, filter: &'a str) -> [&'a str; 6] {
+    ["package", "compile", "-m", filter, "-f", package]
+}
+
+/// Runs `cmd package compile -m <filter> -f <package>` on the target.
+pub fn compile_package(shell: &Shell, package: &str, filter: &str) -> CompileOutcome {
+    match shell.output(&mut shell.privileged("cmd", &compile_args(package, filter))) {
+        Ok(output) if output.status.success() => CompileOutcome::Compiled,
+        Ok(output) => CompileOutcome::Failed(String::from_utf8_lossy(&output.stderr).trim().to_string()),
+        Err(e) => CompileOutcome::Failed(e.to_string()),
+    }
+}
+
+/// Compiles `packages` on the rayon pool, so at most `--jobs` dex2oat runs
+/// happen at once. Once `stop` is set, packages that haven't started yet are
+/// reported as [`CompileOutcome::Cancelled`]; `on_done` is called after each
+/// package either way. Results keep the order of `packages`.
+pub fn compile_all<'a>(
+    shell: &Shell,
+    packages: &[&'a str],
+    filter: &str,
+    stop: &AtomicBool,
+    on_done: impl Fn(&str) + Sync,
+) -> Vec<(&'a str, CompileOutcome)> {
+    packages
+        .par_iter()
+        .map(|&package| {
+            let outcome = if stop.load(Ordering::SeqCst) {
+                CompileOutcome::Cancelled
+            } else {
+                compile_package(shell, package, filter)
+            };
+            on_done(package);
+            (package, outcome)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzer;
+
+    #[test]
+    fn test_package_list_parsing() {
+        let sample = r#"
+package:/data/app/~~abc==/com.example.app-xyz==/base.apk=com.example.app
+package:/system/app/Calculator/Calculator.apk=com.android.calculator2
+garbage line
+"#;
+        let list = Package::parse_list(sample);
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].name, "com.android.calculator2");
+        assert_eq!(list[1].name, "com.example.app");
+        assert_eq!(list[1].path, "/data/app/~~abc==/com.example.app-xyz==/base.apk");
+        assert_eq!(list[0].app_type, AppType::System);
+        assert_eq!(list[1].app_type, AppType::User);
+
+        let garbled = decode_lossy(b"package:/data/app/a/base.apk=com.caf\xe9\npackage:/data/app/\xff/base.apk=com.ok\n", "test");
+        let list = Package::parse_list(&garbled);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].name, "com.ok");
+    }
+
+    #[test]
+    fn test_compile_all() {
+        let names = ["com.a", "com.b", "com.c"];
+        let done = std::sync::Mutex::new(Vec::new());
+        let ok = Shell::local().with_prefix("env").with_program("cmd", "true");
+        let results = compile_all(&ok, &names, "speed", &AtomicBool::new(false), |name| {
+            done.lock().unwrap().push(name.to_string())
+        });
+        assert_eq!(results.iter().map(|r| r.0).collect::<Vec<_>>(), names);
+        assert!(results.iter().all(|r| matches!(r.1, CompileOutcome::Compiled)));
+        assert_eq!(done.lock().unwrap().len(), 3);
+
+        let failing = Shell::local().with_prefix("env").with_program("cmd", "false");
+        let results = compile_all(&failing, &names[..1], "speed", &AtomicBool::new(false), |_| {});
+        assert!(matches!(results[0].1, CompileOutcome::Failed(_)));
+
+        let results = compile_all(&ok, &names, "speed", &AtomicBool::new(true), |_| {});
+        assert!(results.iter().all(|r| matches!(r.1, CompileOutcome::Cancelled)));
+    }
+
+    #[test]
+    fn test_updated_system_classification() {
+        let system = |path: &str| Package { app_type: AppType::System, ..Package::new("com.example", path) };
+        assert!(system("/data/app/~~x==/com.google.android.webview-y==/base.apk").is_updated_system());
+        assert!(system("/data/app/com.android.chrome-1/base.apk").is_updated_system());
+        assert!(!system("/system/app/Calculator/Calculator.apk").is_updated_system());
+        assert!(!system("/product/priv-app/Phonesky/Phonesky.apk").is_updated_system());
+        assert!(!system("/data/preload/Foo.apk").is_updated_system());
+        // A /data/app package pm doesn't list as system is just a user app
+        assert!(!Package::new("com.example", "/data/app/~~x==/com.example-y==/base.apk").is_updated_system());
+    }
+
+    #[test]
+    fn test_installer_parsing() {
+        let sample = "package:/data/app/~~a=b==/com.play-c==/base.apk=com.play  installer=com.android.vending\n\
+                      package:/data/app/~~d==/com.side-e==/base.apk=com.side  installer=null\n\
+                      package:/system/app/Calc/Calc.apk=com.android.calculator2\n";
+        let list = Package::parse_list(sample);
+        let find = |name: &str| list.iter().find(|p| p.name == name).unwrap();
+        assert_eq!(find("com.play").installer.as_deref(), Some("com.android.vending"));
+        assert_eq!(find("com.play").path, "/data/app/~~a=b==/com.play-c==/base.apk");
+        assert_eq!(find("com.side").installer, None);
+        assert_eq!(find("com.android.calculator2").installer, None);
+    }
+
+    #[test]
+    fn test_disabled_listing() {
+        let dir = std::env::temp_dir().join(format!("dexter-pm-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pm = dir.join("pm");
+        std::fs::write(
+            &pm,
+            "#!/bin/sh\ncase \"$*\" in\n  *-d*) echo package:com.off ;;\n  \
+             *) echo package:/data/app/a/base.apk=com.on; echo package:/data/app/b/base.apk=com.off ;;\nesac\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&pm, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+
+        let shell = Shell::local().with_prefix("env").with_program("pm", &pm.to_string_lossy());
+        let list = Package::fetch_list(&shell, AppType::User, None, None).unwrap();
+        let states: Vec<(&str, bool)> = list.iter().map(|p| (p.name.as_str(), p.enabled)).collect();
+        assert_eq!(states, [("com.off", false), ("com.on", true)]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_args() {
+        assert_eq!(Package::list_args(AppType::User, None, &["-f"]), ["list", "packages", "-f", "-3"]);
+        assert_eq!(Package::list_args(AppType::All, Some(10), &["-f"]), ["list", "packages", "-f", "--user", "10"]);
+        assert_eq!(Package::list_args(AppType::System, Some(0), &[]), ["list", "packages", "-s", "--user", "0"]);
+
+        // What fetch_list asks pm for: `All` adds no type flag rather than an empty argument
+        let expected: [(AppType, &[&str]); 3] = [
+            (AppType::User, &["list", "packages", "-f", "-i", "-3"]),
+            (AppType::System, &["list", "packages", "-f", "-i", "-s"]),
+            (AppType::All, &["list", "packages", "-f", "-i"]),
+        ];
+        for (app_type, argv) in expected {
+            let args = Package::list_args(app_type, None, &["-f", "-i"]);
+            assert_eq!(args, argv, "{}", app_type);
+            assert!(args.iter().all(|a| !a.trim().is_empty()));
+            // pm gets them as separate arguments, not as one shell command line
+            let cmd = Shell::local().command("pm", &args.iter().map(String::as_str).collect::<Vec<_>>());
+            assert_eq!(cmd.get_program(), "pm");
+            assert_eq!(cmd.get_args().collect::<Vec<_>>(), argv);
+        }
+    }
+
+    #[test]
+    fn test_split_apk_prefers_base() {
+        let sample = r#"
+package:/data/app/~~x==/com.example.app-y==/split_config.arm64_v8a.apk=com.example.app
+package:/data/app/~~x==/com.example.app-y==/base.apk=com.example.app
+"#;
+        let list = Package::parse_list(sample);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].path, "/data/app/~~x==/com.example.app-y==/base.apk");
+
+        let split = Package::new("com.example.app", "/data/app/~~x==/com.example.app-y==/split_config.en.apk");
+        assert_eq!(split.base_apk(), PathBuf::from("/data/app/~~x==/com.example.app-y==/base.apk"));
+    }
+
+    #[test]
+    fn test_artifact_mtime() {
+        let dir = std::env::temp_dir().join(format!("dexter-oat-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("oat/arm64")).unwrap();
+        std::fs::write(dir.join("base.apk"), b"apk").unwrap();
+        std::fs::write(dir.join("oat/arm64/base.odex"), b"odex").unwrap();
+        let pkg = Package::new("com.example.app", dir.join("base.apk").to_string_lossy().into_owned());
+
+        assert!(pkg.artifact_mtime(Abi::Arm64).is_some());
+        assert!(pkg.artifact_mtime(Abi::Arm).is_none());
+        assert!(pkg.artifact_mtime(Abi::Unknown).is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_has_compiled_artifact() {
+        let dir = std::env::temp_dir().join(format!("dexter-verify-test-{}", std::process::id()));
+        let cache = dir.join("dalvik-cache");
+        std::fs::create_dir_all(dir.join("app/oat/arm64")).unwrap();
+        std::fs::create_dir_all(cache.join("arm")).unwrap();
+        std::fs::write(dir.join("app/base.apk"), b"apk").unwrap();
+        std::fs::write(dir.join("app/oat/arm64/base.odex"), b"odex").unwrap();
+        let apk = dir.join("app/base.apk").to_string_lossy().into_owned();
+        let cache_name = format!("{}@classes.dex", apk.trim_start_matches('/').replace('/', "@"));
+        let pkg = Package::new("com.example.app", apk);
+
+        assert_eq!(pkg.has_compiled_artifact(Abi::Arm64, &cache), Some(true));
+        assert_eq!(pkg.has_compiled_artifact(Abi::Arm, &cache), Some(false));
+        std::fs::write(cache.join("arm").join(cache_name), b"odex").unwrap();
+        assert_eq!(pkg.has_compiled_artifact(Abi::Arm, &cache), Some(true));
+        // A vdex alone holds no compiled code
+        std::fs::create_dir_all(dir.join("app/oat/x86_64")).unwrap();
+        std::fs::write(dir.join("app/oat/x86_64/base.vdex"), b"vdex").unwrap();
+        assert_eq!(pkg.has_compiled_artifact(Abi::X86_64, &cache), Some(false));
+        assert_eq!(pkg.has_compiled_artifact(Abi::Unknown, &cache), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_artifact_size() {
+        let dir = std::env::temp_dir().join(format!("dexter-size-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("oat/arm64")).unwrap();
+        std::fs::create_dir_all(dir.join("oat/arm")).unwrap();
+        std::fs::write(dir.join("base.apk"), b"apk").unwrap();
+        std::fs::write(dir.join("oat/arm64/base.odex"), [0; 100]).unwrap();
+        std::fs::write(dir.join("oat/arm64/base.vdex"), [0; 20]).unwrap();
+        std::fs::write(dir.join("oat/arm64/base.art"), [0; 3]).unwrap();
+        std::fs::write(dir.join("oat/arm/base.odex"), [0; 7]).unwrap();
+        // Not an artifact
+        std::fs::write(dir.join("oat/arm64/base.prof"), [0; 50]).unwrap();
+        let pkg = Package::new("com.example.app", dir.join("base.apk").to_string_lossy().into_owned());
+        assert_eq!(pkg.artifact_size(), Some(130));
+
+        std::fs::remove_dir_all(dir.join("oat")).unwrap();
+        assert_eq!(pkg.artifact_size(), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_label_cache_round_trip() {
+        let dir = std::env::temp_dir().join(format!("dexter-cache-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let apk = dir.join("base.apk");
+        std::fs::write(&apk, b"not really an apk").unwrap();
+        let cache_path = dir.join("labels.json");
+        let pkg = Package::new("com.example.app", apk.to_string_lossy().into_owned());
+
+        let shell = Shell::local();
+        let mut cache = LabelCache::load(&cache_path);
+        assert_eq!(cache.lookup(&pkg, &shell), None);
+        assert!(cache.insert(&pkg, Some("Example".to_string()), &shell));
+        cache.save().unwrap();
+
+        let reloaded = LabelCache::load(&cache_path);
+        assert_eq!(reloaded.lookup(&pkg, &shell), Some(Some("Example".to_string())));
+
+        // A changed APK invalidates the entry
+        std::fs::write(&apk, b"a different, longer payload").unwrap();
+        assert_eq!(reloaded.lookup(&pkg, &shell), None);
+        // A disk cache can't store what it can't fingerprint
+        let mut cache = LabelCache::load(&cache_path);
+        assert!(!cache.insert(&Package::new("com.gone", dir.join("gone.apk").to_string_lossy().into_owned()), None, &shell));
+        // Failures are typed, with the path that couldn't be written
+        let unwritable = LabelCache::load(&dir.join("missing-dir").join("labels.json"));
+        assert!(matches!(unwritable.save(), Err(DexoptError::FileWrite { what: "label cache", .. })));
+        // The temporary file is private to this run and never left behind
+        std::fs::write(dir.join("labels.tmp"), "someone else's").unwrap();
+        LabelCache::load(&cache_path).save().unwrap();
+        let occupied = dir.join("occupied");
+        std::fs::create_dir(&occupied).unwrap();
+        assert!(LabelCache::load(&occupied).save().is_err());
+        assert_eq!(std::fs::read_to_string(dir.join("labels.tmp")).unwrap(), "someone else's");
+        assert!(std::fs::read_dir(&dir).unwrap().all(|e| !e.unwrap().file_name().to_string_lossy().ends_with(&format!(".{}.tmp", std::process::id()))));
+
+        // The in-memory cache never reads the APK (it may be on another device)
+        // and tells an updated install apart by its new path
+        let remote = Package::new("com.example.app", "/data/app/~~abc==/com.example.app-1/base.apk");
+        let mut session = LabelCache::in_memory();
+        assert!(session.insert(&remote, Some("Example".to_string()), &shell));
+        assert_eq!(session.lookup(&remote, &shell), Some(Some("Example".to_string())));
+        assert_eq!(session.lookup(&Package::new("com.example.app", "/data/app/~~def==/com.example.app-2/base.apk"), &shell), None);
+
+        assert_eq!(parse_stat("24589123 1717171717\n"), Some((24589123, 1717171717)));
+        assert_eq!(parse_stat("stat: '/data/app/x': No such file or directory"), None);
+        assert_eq!(parse_stat("12 34 56"), None);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_label_heuristic_filters_class_names() {
+        let too_long = "a".repeat(65);
+        let cases: &[(&str, bool)] = &[
+            ("com.example.SomeActivity", false),                        // class-like
+            ("com.foo.bar", false),                                     // package-like
+            ("com.foo.Outer$Inner", false),                             // nested class
+            ("@0x1040001", false),                                      // resource ref
+            ("@string/app_name", false),                                // unresolved string ref
+            ("https://www.facebook.com/.well-known/assetlinks.json", false), // URL
+            ("[{ \"include\": \"https://example.com\" }]", false), // JSON blob
+            ("{ \"key\": \"value\" }", false),                     // JSON object
+            (too_long.as_str(), false),                                 // too long
+            ("My Cool App", true),                                      // real label
+            ("MyApp", true),                                            // simple word
+            ("Calculator", true),                                       // simple word
+            ("Facebook", true),                                         // real label
+            ("Booking.com", true),                                      // dotted brand name
+            ("1.1.1.1", true),                                          // numeric brand name
+            ("Mr. Robot", true),                                        // dot followed by a space
+            ("com.example Camera", true),                               // free text, not an identifier
+        ];
+
+        for (label, should_keep) in cases {
+            assert_eq!(
+                Package::is_valid_label(label),
+                *should_keep,
+                "Failed for label: {:?}", label
+            );
+        }
+    }
+
+    #[test]
+    fn test_clean_label() {
+        assert_eq!(clean_label("  Camera \n"), Some("Camera".to_string()));
+        assert_eq!(clean_label("Two\nLines"), Some("Two Lines".to_string()));
+        assert_eq!(clean_label("   "), None);
+        assert_eq!(clean_label(" @string/app_name"), None);
+        assert_eq!(clean_label("org.example.app"), None);
+    }
+
+    #[test]
+    fn test_name_matcher() {
+        let sub = NameMatcher::new("google", false).unwrap();
+        assert!(sub.matches("com.google.android.gms"));
+        assert!(!sub.matches("com.android.chrome"));
+
+        let re = NameMatcher::new(r"^com\.android\.", true).unwrap();
+        assert!(re.matches("com.android.chrome"));
+        assert!(!re.matches("org.com.android.x"));
+
+        // Regex metacharacters are literal in substring mode
+        assert!(!NameMatcher::new("^com", false).unwrap().matches("com.foo"));
+        assert!(NameMatcher::new("(unclosed", true).is_err());
+
+        let glob = NameMatcher::glob("com.google.*").unwrap();
+        assert!(glob.matches("com.google.android.gms"));
+        assert!(!glob.matches("com.googlex"));
+        assert!(!glob.matches("org.com.google.x"));
+        assert!(NameMatcher::glob("*.camera").unwrap().matches("com.oem.camera"));
+        assert!(matches!(NameMatcher::glob("com.[a"), Err(DexoptError::ParseError { what: "glob", .. })));
+
+        let list = NameMatcher::parse_list("# audit scope\ncom.android.chrome\n\n  org.fdroid.*  \n").unwrap();
+        assert_eq!(list.len(), 2);
+        let listed = |name| list.iter().any(|m| m.matches(name));
+        assert!(listed("com.android.chrome"));
+        // Plain names match exactly, not as substrings
+        assert!(!listed("com.android.chromex"));
+        assert!(listed("org.fdroid.fdroid"));
+        assert!(NameMatcher::parse_list("### \n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_structured_errors() {
+        assert!(matches!(
+            NameMatcher::new("(unclosed", true),
+            Err(DexoptError::ParseError { what: "regex", .. })
+        ));
+
+        let missing = Path::new("/nonexistent/dexter-dump.txt");
+        match Analyzer::fetch_dump(&Shell::local(), Some(missing)) {
+            Err(DexoptError::FileRead { path, source, .. }) => {
+                assert_eq!(path, missing);
+                assert_eq!(source.kind(), io::ErrorKind::NotFound);
+            }
+            other => panic!("expected FileRead, got {:?}", other.map(|_| ())),
+        }
+    }
+}
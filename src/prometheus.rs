@@ -0,0 +1,115 @@
+//! Prometheus text exposition output (`--format prometheus`), for scraping
+//! through node_exporter's textfile collector.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::analyzer::Dimension;
+use crate::package::AppType;
+use crate::ui::{DisplayRow, UI};
+
+/// Escapes a label value: backslashes, double quotes and line feeds.
+pub fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Writes one gauge family with its HELP and TYPE lines. `series` pairs a
+/// label value with its sample; an empty `label` writes a single unlabelled
+/// sample.
+fn family<'a>(out: &mut String, name: &str, help: &str, label: &str, series: impl IntoIterator<Item = (&'a str, usize)>) {
+    // Writing to a String can't fail
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+    for (value, count) in series {
+        if label.is_empty() {
+            let _ = writeln!(out, "{} {}", name, count);
+        } else {
+            let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, escape_label(value), count);
+        }
+    }
+}
+
+/// Renders the metrics for the checked packages. Packages are counted once
+/// by their least optimized status (`none` without dexopt data); `stats` is
+/// the per-entry summary breakdown by `summary_by`.
+pub fn render(rows: &[DisplayRow], stats: &BTreeMap<String, usize>, summary_by: Dimension) -> String {
+    let mut by_type: BTreeMap<AppType, usize> = BTreeMap::new();
+    let mut by_status: BTreeMap<&str, usize> = BTreeMap::new();
+    for (pkg, _, info_list) in rows {
+        *by_type.entry(pkg.app_type).or_insert(0) += 1;
+        let worst = info_list
+            .and_then(|infos| infos.iter().min_by_key(|i| UI::status_score(&i.status)))
+            .map_or("none", |i| i.status.as_str());
+        *by_status.entry(worst).or_insert(0) += 1;
+    }
+    let types: Vec<(String, usize)> = by_type.into_iter().map(|(t, n)| (t.to_string().to_lowercase(), n)).collect();
+
+    let mut out = String::new();
+    family(&mut out, "dexopt_packages_total", "Packages checked, by app type.", "type", types.iter().map(|(t, n)| (t.as_str(), *n)));
+    family(
+        &mut out,
+        "dexopt_packages_by_status_total",
+        "Packages checked, by their least optimized status (none: no dexopt data).",
+        "status",
+        by_status,
+    );
+    let (label, help) = match summary_by {
+        Dimension::Status => ("status", "Dexopt entries (one per ABI and dex file), by status."),
+        Dimension::Reason => ("reason", "Dexopt entries (one per ABI and dex file), by compilation reason."),
+    };
+    family(&mut out, "dexopt_entries_total", help, label, stats.iter().map(|(k, n)| (k.as_str(), *n)));
+    family(
+        &mut out,
+        "dexopt_packages_missing_data_total",
+        "Packages checked that have no dexopt data.",
+        "",
+        [("", rows.iter().filter(|row| row.2.is_none()).count())],
+    );
+    family(
+        &mut out,
+        "dexopt_packages_with_errors_total",
+        "Packages checked with at least one failed dexopt entry.",
+        "",
+        [("", rows.iter().filter(|row| UI::has_error(row.2)).count())],
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzer;
+    use crate::package::Package;
+
+    #[test]
+    fn test_prometheus_exposition() {
+        let analyzer = Analyzer::new("[com.a]\n  arm64: [status=speed-profile]\n  arm: [status=verify]\n[com.s]\n  arm64: [status=speed]\n");
+        let pkgs = [
+            Package::new("com.a", "/data/app/a/base.apk"),
+            Package::new("com.s", "/system/app/s/s.apk"),
+            Package::new("com.b", "/data/app/b/base.apk"),
+        ];
+        let rows: Vec<DisplayRow> = pkgs.iter().map(|p| (p, None, analyzer.get_info(&p.name))).collect();
+        let stats = BTreeMap::from([("speed".to_string(), 1), ("speed-profile".to_string(), 1), ("verify".to_string(), 1)]);
+
+        let text = render(&rows, &stats, Dimension::Status);
+        assert!(text.contains("# HELP dexopt_packages_total Packages checked, by app type.\n# TYPE dexopt_packages_total gauge\n"));
+        assert!(text.contains("dexopt_packages_total{type=\"user\"} 2\n"));
+        assert!(text.contains("dexopt_packages_total{type=\"system\"} 1\n"));
+        assert!(text.contains("dexopt_packages_by_status_total{status=\"verify\"} 1\n"));
+        assert!(text.contains("dexopt_packages_by_status_total{status=\"none\"} 1\n"));
+        assert!(text.contains("dexopt_entries_total{status=\"speed-profile\"} 1\n"));
+        assert!(text.contains("dexopt_packages_missing_data_total 1\n"));
+        assert!(text.contains("dexopt_packages_with_errors_total 0\n"));
+        // Every sample belongs to a family announced just before it
+        let mut family = "";
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                family = rest.split(' ').next().unwrap();
+            } else if !line.starts_with('#') {
+                assert!(line.starts_with(family), "{}", line);
+            }
+        }
+
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
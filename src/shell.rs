@@ -0,0 +1,427 @@
+//! Running `pm`, `dumpsys` and friends locally or over ADB.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::process::{Command, Output, Stdio};
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+
+use crate::error::DexoptError;
+
+/// Exit status of a shell (`su -c`, `adb shell`, ...) asked to run a
+/// program that doesn't exist.
+const EXIT_NOT_FOUND: i32 = 127;
+
+/// Wait before the first retry in [`Shell::output_with_retries`]; doubles each time.
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(250);
+
+/// Where external commands are executed.
+#[derive(Debug, Clone)]
+pub enum Target {
+    /// Directly on this device (Termux / on-device shell).
+    Local,
+    /// On a device reached through `adb -s <serial> shell`.
+    Adb { serial: String },
+}
+
+/// Builds and runs external commands against a [`Target`], enforcing an
+/// optional timeout so a hung `dumpsys` or `aapt` can't freeze the tool.
+#[derive(Debug, Clone)]
+pub struct Shell {
+    target: Target,
+    timeout: Option<Duration>,
+    /// Wrapper every command runs under, e.g. `["su", "-c"]`.
+    prefix: Vec<String>,
+    /// Binary overrides by program name, e.g. `pm` -> `/system/bin/pm`.
+    programs: HashMap<String, String>,
+    /// Extra attempts for [`Shell::output_with_retries`].
+    retries: u32,
+}
+
+impl Shell {
+    fn new(target: Target) -> Self {
+        Shell { target, timeout: None, prefix: Vec::new(), programs: HashMap::new(), retries: 0 }
+    }
+
+    pub fn local() -> Self {
+        Self::new(Target::Local)
+    }
+
+    /// Resolves the ADB device to talk to. Without an explicit serial this
+    /// requires exactly one attached device.
+    pub fn adb(serial: Option<String>) -> Result<Self, DexoptError> {
+        let adb = |serial| Self::new(Target::Adb { serial });
+        if let Some(serial) = serial {
+            return Ok(adb(serial));
+        }
+
+        let output = Command::new("adb")
+            .arg("devices")
+            .output()
+            .map_err(|e| DexoptError::command("adb", e))?;
+        let devices = Self::parse_adb_devices(&String::from_utf8_lossy(&output.stdout));
+
+        match devices.as_slice() {
+            [] => Err(DexoptError::NoDevice(
+                "No ADB device found. Connect a device and enable USB debugging.".to_string(),
+            )),
+            [serial] => Ok(adb(serial.clone())),
+            _ => Err(DexoptError::NoDevice(format!(
+                "Multiple ADB devices attached, pick one with --serial: {}",
+                devices.join(", ")
+            ))),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Runs every command under `prefix` (split on whitespace). A prefix ending
+    /// in `-c`, like `su -c`, receives the whole command line as one argument.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.split_whitespace().map(str::to_string).collect();
+        self
+    }
+
+    /// Uses `path` whenever `program` is run.
+    pub fn with_program(mut self, program: &str, path: &str) -> Self {
+        self.programs.insert(program.to_string(), path.to_string());
+        self
+    }
+
+    /// Whether commands run on another device, whose files this process can't read.
+    pub fn is_remote(&self) -> bool {
+        matches!(self.target, Target::Adb { .. })
+    }
+
+    /// The binary run for `program`, after any [`Shell::with_program`] override.
+    pub fn program<'a>(&'a self, program: &'a str) -> &'a str {
+        self.programs.get(program).map_or(program, String::as_str)
+    }
+
+    /// Where `program` is installed on the target, as reported by `which`.
+    pub fn which(&self, program: &str) -> Option<String> {
+        let output = self.output(&mut self.command("which", &[program])).ok()?;
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        (output.status.success() && !path.is_empty()).then_some(path)
+    }
+
+    /// Extracts the serials of ready devices from `adb devices` output.
+    pub fn parse_adb_devices(raw: &str) -> Vec<String> {
+        raw.lines()
+            .skip_while(|l| !l.starts_with("List of devices"))
+            .skip(1)
+            .filter_map(|l| {
+                let (serial, state) = l.split_once('\t')?;
+                (state.trim() == "device").then(|| serial.trim().to_string())
+            })
+            .collect()
+    }
+
+    /// Builds a command that runs `program` with `args` on the target.
+    pub fn command(&self, program: &str, args: &[&str]) -> Command {
+        self.build(&self.prefix, program, args)
+    }
+
+    /// Like [`Shell::command`], with elevated privileges: `su -c` locally
+    /// unless a prefix is configured, the ADB shell user remotely.
+    pub fn privileged(&self, program: &str, args: &[&str]) -> Command {
+        match self.target {
+            Target::Local if self.prefix.is_empty() => self.build(&["su".to_string(), "-c".to_string()], program, args),
+            _ => self.command(program, args),
+        }
+    }
+
+    /// The single place external command lines are assembled, so prefixes and
+    /// binary overrides apply to every invocation. Arguments stay separate
+    /// argv entries; only where a shell must parse them anyway (a `... -c`
+    /// prefix, `adb shell`) is each one quoted, so none is ever reinterpreted.
+    fn build(&self, wrapper: &[String], program: &str, args: &[&str]) -> Command {
+        let program = self.program(program);
+        let mut argv: Vec<String> = std::iter::once(program).chain(args.iter().copied()).map(str::to_string).collect();
+        if wrapper.last().is_some_and(|w| w == "-c") {
+            let cmdline = argv.iter().map(|a| shell_quote(a)).collect::<Vec<_>>().join(" ");
+            argv = wrapper.iter().cloned().chain(std::iter::once(cmdline)).collect();
+        } else {
+            argv = wrapper.iter().cloned().chain(argv).collect();
+        }
+
+        match self.target {
+            Target::Local => {
+                let mut cmd = Command::new(&argv[0]);
+                cmd.args(&argv[1..]);
+                cmd
+            }
+            Target::Adb { ref serial } => {
+                // adb joins its arguments into one remote command line
+                let mut cmd = Command::new("adb");
+                cmd.arg("-s").arg(serial).arg("shell").args(argv.iter().map(|a| shell_quote(a)));
+                cmd
+            }
+        }
+    }
+
+    /// Like [`Shell::output`], but retries with exponential backoff while `cmd`
+    /// fails to run, exits unsuccessfully or prints nothing, logging each retry
+    /// of `what` to stderr. The last attempt's result is returned as is.
+    pub fn output_with_retries(&self, what: &str, cmd: &mut Command) -> io::Result<Output> {
+        let mut delay = RETRY_BASE_DELAY;
+        for attempt in 1..=self.retries {
+            let problem = match self.output(cmd) {
+                Ok(output) if output.status.success() && !output.stdout.is_empty() => return Ok(output),
+                // A missing binary won't appear on the next attempt
+                Ok(output) if output.status.code() == Some(EXIT_NOT_FOUND) => return Ok(output),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(e),
+                Ok(output) if !output.status.success() => output.status.to_string(),
+                Ok(_) => "empty output".to_string(),
+                Err(e) => e.to_string(),
+            };
+            eprintln!(
+                "{}",
+                format!(
+                    "Warning: {} failed ({}), retrying in {}ms (attempt {}/{})",
+                    what,
+                    problem,
+                    delay.as_millis(),
+                    attempt + 1,
+                    self.retries + 1
+                )
+                .yellow()
+            );
+            std::thread::sleep(delay);
+            delay *= 2;
+        }
+        self.output(cmd)
+    }
+
+    /// [`Shell::output_with_retries`] for a required tool: a missing `program`
+    /// becomes [`DexoptError::ToolMissing`] and an unsuccessful exit
+    /// [`DexoptError::CommandExit`], instead of an empty result.
+    pub fn checked_output(&self, what: &str, program: &str, cmd: &mut Command) -> Result<Output, DexoptError> {
+        let missing = || DexoptError::ToolMissing { program: self.program(program).to_string() };
+        let output = match self.output_with_retries(what, cmd) {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Err(missing()),
+            Err(e) => return Err(DexoptError::command(program, e)),
+        };
+        if output.status.code() == Some(EXIT_NOT_FOUND) {
+            return Err(missing());
+        }
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(DexoptError::CommandExit {
+                command: what.to_string(),
+                status: output.status.to_string(),
+                stderr: stderr.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default().to_string(),
+            });
+        }
+        Ok(output)
+    }
+
+    /// Runs `cmd` to completion and captures its output. When a timeout is
+    /// configured the child is killed once it expires and `TimedOut` is returned.
+    pub fn output(&self, cmd: &mut Command) -> io::Result<Output> {
+        let Some(timeout) = self.timeout else {
+            return cmd.output();
+        };
+
+        let mut child = cmd.stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+        // Drain both pipes on their own threads so a chatty child can't block on a full pipe
+        fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<Vec<u8>> {
+            std::thread::spawn(move || {
+                let mut buf = Vec::new();
+                if let Some(mut pipe) = pipe {
+                    let _ = pipe.read_to_end(&mut buf);
+                }
+                buf
+            })
+        }
+        let stdout = drain(child.stdout.take());
+        let stderr = drain(child.stderr.take());
+
+        let deadline = Instant::now() + timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("timed out after {}s", timeout.as_secs()),
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        };
+
+        Ok(Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        })
+    }
+}
+
+/// Quotes `arg` for a POSIX shell, leaving plain words untouched.
+fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "_-./:=@%+,~".contains(c));
+    if plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Decodes `bytes` (command output or a saved capture) as UTF-8, replacing
+/// invalid sequences with U+FFFD, and warns once on stderr when `what` had any.
+pub fn decode_lossy(bytes: &[u8], what: &str) -> String {
+    match String::from_utf8_lossy(bytes) {
+        Cow::Borrowed(text) => text.to_string(),
+        Cow::Owned(text) => {
+            eprintln!("{}", format!("Warning: {} contained invalid UTF-8; the affected characters were replaced.", what).yellow());
+            text
+        }
+    }
+}
+
+/// Reports the calling identity via `id`, e.g. `uid=2000(shell)`.
+pub fn current_uid(shell: &Shell) -> Option<String> {
+    let output = shell.output(&mut shell.command("id", &[])).ok()?;
+    let raw = String::from_utf8_lossy(&output.stdout);
+    raw.split_whitespace().next().map(str::to_string)
+}
+
+/// The device's API level, from `getprop ro.build.version.sdk`.
+pub fn sdk_level(shell: &Shell) -> Option<u32> {
+    let output = shell.output(&mut shell.command("getprop", &["ro.build.version.sdk"])).ok()?;
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adb_devices_parsing() {
+        let sample = "* daemon started successfully\nList of devices attached\nR58M123ABC\tdevice\nemulator-5554\toffline\n192.168.1.5:5555\tdevice\n\n";
+        assert_eq!(
+            Shell::parse_adb_devices(sample),
+            vec!["R58M123ABC".to_string(), "192.168.1.5:5555".to_string()]
+        );
+        assert!(Shell::parse_adb_devices("List of devices attached\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_decode_lossy() {
+        assert_eq!(decode_lossy("caf\u{e9}".as_bytes(), "test"), "caf\u{e9}");
+        assert_eq!(decode_lossy(b"caf\xe9!", "test"), "caf\u{fffd}!");
+    }
+
+    #[test]
+    fn test_command_builder() {
+        let argv = |cmd: &Command| {
+            std::iter::once(cmd.get_program())
+                .chain(cmd.get_args())
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let plain = Shell::local().with_program("pm", "/system/bin/pm");
+        assert_eq!(argv(&plain.command("pm", &["list", "packages"])), ["/system/bin/pm", "list", "packages"]);
+        assert_eq!(argv(&plain.privileged("cmd", &["package", "bg-dexopt-job"])), ["su", "-c", "cmd package bg-dexopt-job"]);
+        assert_eq!(plain.program("pm"), "/system/bin/pm");
+        assert_eq!(plain.program("aapt"), "aapt");
+
+        let wrapped = Shell::local().with_prefix("su -c").with_program("dumpsys", "/system/bin/dumpsys");
+        assert_eq!(argv(&wrapped.command("dumpsys", &["package", "dexopt"])), ["su", "-c", "/system/bin/dumpsys package dexopt"]);
+        assert_eq!(argv(&wrapped.command("aapt", &["dump", "/data/My App/base.apk"]))[2], "aapt dump '/data/My App/base.apk'");
+
+        let nice = Shell::local().with_prefix("nice -n 5");
+        assert_eq!(argv(&nice.command("pm", &["list"])), ["nice", "-n", "5", "pm", "list"]);
+
+        let adb = Shell::new(Target::Adb { serial: "R58M".to_string() }).with_prefix("su -c");
+        assert_eq!(argv(&adb.command("pm", &["list", "packages"])), ["adb", "-s", "R58M", "shell", "su", "-c", "'pm list packages'"]);
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn test_arguments_are_not_reinterpreted() {
+        let marker = std::env::temp_dir().join(format!("dexter-injected-{}", std::process::id()));
+        let hostile = format!("x; touch {}", marker.display());
+        for shell in [Shell::local().with_prefix("env"), Shell::local().with_prefix("sh -c")] {
+            let output = shell.output(&mut shell.command("echo", &[&hostile, "$HOME", "a  b", ""])).unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{} $HOME a  b \n", hostile));
+        }
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_command_timeout() {
+        let shell = Shell::local().with_timeout(Some(Duration::from_secs(1)));
+
+        let mut fast = Command::new("echo");
+        fast.arg("hello");
+        let output = shell.output(&mut fast).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "hello");
+
+        let mut slow = Command::new("sleep");
+        slow.arg("5");
+        let err = shell.output(&mut slow).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn test_missing_tool_is_reported() {
+        let shell = Shell::local();
+        let mut direct = shell.command("dexter-no-such-tool", &[]);
+        assert!(matches!(
+            shell.checked_output("probe", "dexter-no-such-tool", &mut direct),
+            Err(DexoptError::ToolMissing { ref program }) if program == "dexter-no-such-tool"
+        ));
+
+        // Through a wrapper the shell reports it with exit status 127
+        let wrapped = Shell::local().with_prefix("sh -c");
+        let mut cmd = wrapped.command("dexter-no-such-tool", &[]);
+        assert!(matches!(wrapped.checked_output("probe", "dexter-no-such-tool", &mut cmd), Err(DexoptError::ToolMissing { .. })));
+
+        let mut failing = Command::new("sh");
+        failing.args(["-c", "echo 'permission denied' >&2; exit 3"]);
+        match shell.checked_output("probe", "sh", &mut failing) {
+            Err(e @ DexoptError::CommandExit { .. }) => {
+                assert_eq!(e.to_string(), "'probe' failed (exit status: 3): permission denied");
+            }
+            other => panic!("expected CommandExit, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_command_retries() {
+        let marker = std::env::temp_dir().join(format!("dexter-retry-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        // Fails the first time, succeeds once the marker exists
+        let script = format!("if [ -e '{0}' ]; then echo ok; else touch '{0}'; exit 1; fi", marker.display());
+        let flaky = || {
+            let mut cmd = Command::new("sh");
+            cmd.args(["-c", &script]);
+            cmd
+        };
+
+        let output = Shell::local().output_with_retries("flaky", &mut flaky()).unwrap();
+        assert!(!output.status.success(), "no retries by default");
+
+        let output = Shell::local().with_retries(2).output_with_retries("flaky", &mut flaky()).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "ok");
+        std::fs::remove_file(&marker).unwrap();
+    }
+}
@@ -0,0 +1,249 @@
+//! Classification of dexopt status strings (compiler filters and dump states).
+
+use std::fmt;
+use std::str::FromStr;
+
+use colored::Color;
+use serde::Serialize;
+
+use crate::error::DexoptError;
+
+/// A status reported in a dexopt line's `status=`/`filter=` field.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Status {
+    AssumeVerified,
+    Extract,
+    Verify,
+    Quicken,
+    SpaceProfile,
+    Space,
+    SpeedProfile,
+    Speed,
+    EverythingProfile,
+    Everything,
+    RunFromApk,
+    RunFromApkFallback,
+    Error,
+    Unknown,
+}
+
+/// Coarse grouping of statuses by how much compiled code the app has.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The dexopt attempt failed
+    Error,
+    /// Interpreted straight from the APK
+    Unoptimized,
+    /// Verified or partially compiled
+    Partial,
+    /// Compiled ahead of time
+    Optimized,
+}
+
+impl Status {
+    /// Every status, in the order `dumpsys` documents them.
+    pub const ALL: [Status; 14] = [
+        Status::AssumeVerified,
+        Status::Extract,
+        Status::Verify,
+        Status::Quicken,
+        Status::SpaceProfile,
+        Status::Space,
+        Status::SpeedProfile,
+        Status::Speed,
+        Status::EverythingProfile,
+        Status::Everything,
+        Status::RunFromApk,
+        Status::RunFromApkFallback,
+        Status::Error,
+        Status::Unknown,
+    ];
+
+    /// The name used in dumps and on the command line.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Status::AssumeVerified => "assume-verified",
+            Status::Extract => "extract",
+            Status::Verify => "verify",
+            Status::Quicken => "quicken",
+            Status::SpaceProfile => "space-profile",
+            Status::Space => "space",
+            Status::SpeedProfile => "speed-profile",
+            Status::Speed => "speed",
+            Status::EverythingProfile => "everything-profile",
+            Status::Everything => "everything",
+            Status::RunFromApk => "run-from-apk",
+            Status::RunFromApkFallback => "run-from-apk-fallback",
+            Status::Error => "error",
+            Status::Unknown => "unknown",
+        }
+    }
+
+    /// Parses `raw`, treating anything unrecognised as [`Status::Unknown`].
+    pub fn classify(raw: &str) -> Self {
+        raw.parse().unwrap_or(Status::Unknown)
+    }
+
+    /// Ranks the status by how optimized it is; lower values need attention first.
+    pub fn score(self) -> i32 {
+        match self {
+            Status::Error => -1,
+            Status::RunFromApk | Status::RunFromApkFallback | Status::Unknown => 0,
+            Status::AssumeVerified | Status::Extract | Status::Verify => 1,
+            Status::Quicken | Status::Space | Status::SpaceProfile => 2,
+            Status::Speed => 3,
+            Status::SpeedProfile => 4,
+            Status::Everything | Status::EverythingProfile => 5,
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            Status::Error => Severity::Error,
+            Status::RunFromApk | Status::RunFromApkFallback | Status::Unknown => Severity::Unoptimized,
+            Status::AssumeVerified
+            | Status::Extract
+            | Status::Verify
+            | Status::Quicken
+            | Status::Space
+            | Status::SpaceProfile => Severity::Partial,
+            Status::Speed | Status::SpeedProfile | Status::Everything | Status::EverythingProfile => {
+                Severity::Optimized
+            }
+        }
+    }
+
+    /// The current name of a compiler filter that Android 7 (API 25) and
+    /// earlier reported under an older one, as ART still maps them.
+    pub fn from_legacy(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "verify-none" => Some(Status::AssumeVerified),
+            "verify-at-runtime" => Some(Status::Extract),
+            "verify-profile" => Some(Status::Verify),
+            "interpret-only" => Some(Status::Quicken),
+            "time" => Some(Status::Space),
+            "balanced" => Some(Status::Speed),
+            _ => None,
+        }
+    }
+
+    /// Whether a dump from a device on `api_level` can report this status:
+    /// the Android 8 (API 26) filter names don't exist before it, and
+    /// `quicken` was dropped along with dex-to-dex compilation in Android 12
+    /// (API 31). [`Status::Unknown`] is never expected.
+    pub fn expected_on(self, api_level: u32) -> bool {
+        match self {
+            Status::AssumeVerified | Status::Extract => api_level >= 26,
+            Status::Quicken => (26..31).contains(&api_level),
+            Status::Unknown => false,
+            _ => true,
+        }
+    }
+
+    /// Whether the filter produces ahead-of-time compiled code (an odex),
+    /// rather than only verified dex (a vdex) or nothing at all.
+    pub fn compiles_code(self) -> bool {
+        matches!(
+            self,
+            Status::Space | Status::SpaceProfile | Status::Speed | Status::SpeedProfile | Status::Everything | Status::EverythingProfile
+        )
+    }
+
+    /// One-line explanation of the performance and storage tradeoff, for `--explain`.
+    pub fn description(self) -> &'static str {
+        match self {
+            Status::AssumeVerified => "Not verified or compiled; the dex code is trusted and interpreted. Smallest footprint, slowest.",
+            Status::Extract => "Extracted but not verified; verification runs at app start, slowing it. No compiled code.",
+            Status::Verify => "Verified ahead of time (vdex only), then interpreted and JIT-compiled. Little storage, slower than compiled code.",
+            Status::Quicken => "Verified plus interpreter optimizations (Android 8-11). Small footprint, modest speed-up.",
+            Status::SpaceProfile => "Hot code from the usage profile compiled for size. Small and reasonably fast.",
+            Status::Space => "Everything compiled with size optimizations. Smaller than speed, somewhat slower.",
+            Status::SpeedProfile => "Hot code from the usage profile compiled ahead of time, the rest JIT. The usual balance of speed and storage.",
+            Status::Speed => "Every method compiled ahead of time. Fast from the first launch, but large oat files.",
+            Status::EverythingProfile => "The profiled code compiled with every optimization. Large footprint.",
+            Status::Everything => "Every method compiled with every optimization. Largest footprint; mostly for testing.",
+            Status::RunFromApk => "No usable artifacts; the code is read from the APK and interpreted. Slowest; recompile it.",
+            Status::RunFromApkFallback => "Artifacts were rejected (e.g. stale after an OTA), so the APK is interpreted until recompiled.",
+            Status::Error => "Dexopt failed; the app runs from the APK until a later attempt succeeds.",
+            Status::Unknown => "Not a status dexter knows; see the raw dump line.",
+        }
+    }
+
+    /// Terminal color in the default theme.
+    pub fn color(self) -> Color {
+        match self {
+            Status::SpeedProfile | Status::Speed => Color::Green,
+            Status::Verify => Color::Yellow,
+            Status::Quicken => Color::Blue,
+            Status::RunFromApk | Status::Error => Color::Red,
+            Status::Everything => Color::Magenta,
+            _ => Color::White,
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = DexoptError;
+
+    /// Case-insensitive; only the names in [`Status::ALL`] are accepted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim();
+        Status::ALL
+            .into_iter()
+            .find(|status| status.as_str().eq_ignore_ascii_case(name))
+            .ok_or_else(|| DexoptError::ParseError {
+                what: "status",
+                input: s.to_string(),
+                reason: "not a known dexopt status".to_string(),
+            })
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_round_trip_and_classification() {
+        for status in Status::ALL {
+            assert_eq!(status.to_string().parse::<Status>().unwrap(), status);
+        }
+        assert_eq!("Speed-Profile".parse::<Status>().unwrap(), Status::SpeedProfile);
+        assert!(matches!("fast".parse::<Status>(), Err(DexoptError::ParseError { what: "status", .. })));
+        assert_eq!(Status::classify("fast"), Status::Unknown);
+
+        assert_eq!(Status::SpeedProfile.severity(), Severity::Optimized);
+        assert_eq!(Status::Quicken.severity(), Severity::Partial);
+        assert_eq!(Status::RunFromApkFallback.severity(), Severity::Unoptimized);
+        assert_eq!(Status::Error.severity(), Severity::Error);
+        // Severity agrees with the score ordering
+        for a in Status::ALL {
+            for b in Status::ALL {
+                if a.severity() < b.severity() {
+                    assert!(a.score() < b.score(), "{} vs {}", a, b);
+                }
+            }
+        }
+        assert_eq!(format!("{:<8}|", Status::Verify), "verify  |");
+
+        assert_eq!(Status::from_legacy("interpret-only"), Some(Status::Quicken));
+        assert_eq!(Status::from_legacy("Verify-None"), Some(Status::AssumeVerified));
+        assert_eq!(Status::from_legacy("speed"), None);
+        assert!(Status::Quicken.expected_on(29));
+        assert!(!Status::Quicken.expected_on(34));
+        assert!(!Status::Quicken.expected_on(25));
+        assert!(Status::SpeedProfile.expected_on(34));
+        assert!(!Status::Unknown.expected_on(34));
+        assert!(Status::ALL.iter().all(|s| !s.description().is_empty()));
+        assert!(Status::SpeedProfile.compiles_code());
+        assert!(!Status::Verify.compiles_code());
+        assert!(!Status::RunFromApk.compiles_code());
+    }
+}
@@ -0,0 +1,271 @@
+//! Interactive browser for the analyzed packages (`--tui`).
+
+use std::io;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::analyzer::DexOptInfo;
+use crate::package::NameMatcher;
+use crate::ui::{DisplayRow, MonoStyle, Theme, UI};
+
+/// Whether keystrokes go to the list or to the search box.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Mode {
+    Browse,
+    Search,
+}
+
+struct App<'a, 'r> {
+    rows: &'r [DisplayRow<'a>],
+    theme: Theme,
+    query: String,
+    /// Indices into `rows` that match `query`.
+    visible: Vec<usize>,
+    list: ListState,
+    mode: Mode,
+}
+
+impl<'a, 'r> App<'a, 'r> {
+    fn new(rows: &'r [DisplayRow<'a>], theme: Theme) -> Self {
+        let mut app = App {
+            rows,
+            theme,
+            query: String::new(),
+            visible: Vec::new(),
+            list: ListState::default(),
+            mode: Mode::Browse,
+        };
+        app.refilter();
+        app
+    }
+
+    fn refilter(&mut self) {
+        self.visible = filter_rows(self.rows, &self.query);
+        self.list.select((!self.visible.is_empty()).then_some(0));
+    }
+
+    fn selected(&self) -> Option<&DisplayRow<'a>> {
+        self.list.selected().and_then(|i| self.visible.get(i)).map(|&i| &self.rows[i])
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        if self.visible.is_empty() {
+            return;
+        }
+        let last = self.visible.len() as isize - 1;
+        let current = self.list.selected().unwrap_or(0) as isize;
+        self.list.select(Some((current + delta).clamp(0, last) as usize));
+    }
+
+    /// Applies one key press; returns false when the browser should exit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        match (self.mode, code) {
+            (Mode::Search, KeyCode::Esc | KeyCode::Enter) => self.mode = Mode::Browse,
+            (Mode::Search, KeyCode::Backspace) => {
+                self.query.pop();
+                self.refilter();
+            }
+            (Mode::Search, KeyCode::Char(c)) => {
+                self.query.push(c);
+                self.refilter();
+            }
+            (Mode::Browse, KeyCode::Esc | KeyCode::Char('q')) => return false,
+            (Mode::Browse, KeyCode::Char('/')) => self.mode = Mode::Search,
+            (_, KeyCode::Down) | (Mode::Browse, KeyCode::Char('j')) => self.move_by(1),
+            (_, KeyCode::Up) | (Mode::Browse, KeyCode::Char('k')) => self.move_by(-1),
+            (_, KeyCode::PageDown) => self.move_by(10),
+            (_, KeyCode::PageUp) => self.move_by(-10),
+            (_, KeyCode::Home) => self.move_by(isize::MIN / 2),
+            (_, KeyCode::End) => self.move_by(isize::MAX / 2),
+            _ => {}
+        }
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [search_area, main_area, help_area] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, detail_area] =
+            Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)]).areas(main_area);
+
+        let search_style = match self.mode {
+            Mode::Search => Style::new().fg(Color::Yellow),
+            Mode::Browse => Style::new(),
+        };
+        let search = Paragraph::new(self.query.as_str())
+            .block(Block::bordered().title(" Filter (comma-separated substrings) ").border_style(search_style));
+        frame.render_widget(search, search_area);
+
+        let items: Vec<ListItem> = self
+            .visible
+            .iter()
+            .map(|&i| {
+                let (pkg, _, info_list) = &self.rows[i];
+                let status = worst_status(*info_list).unwrap_or("(no dexopt data)");
+                ListItem::new(Line::from(vec![
+                    Span::raw(pkg.name.clone()),
+                    Span::raw("  "),
+                    Span::styled(status.to_string(), status_style(self.theme, status)),
+                ]))
+            })
+            .collect();
+        let title = format!(" Packages ({}/{}) ", self.visible.len(), self.rows.len());
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let detail = Paragraph::new(self.selected().map(|row| detail_text(row, self.theme)).unwrap_or_default())
+            .block(Block::bordered().title(" Details "))
+            .wrap(Wrap { trim: false });
+        frame.render_widget(detail, detail_area);
+
+        let help = match self.mode {
+            Mode::Browse => "↑/↓ j/k move  PgUp/PgDn page  / search  q/Esc quit",
+            Mode::Search => "type to filter  Backspace delete  Enter/Esc done",
+        };
+        frame.render_widget(Paragraph::new(help).style(Style::new().add_modifier(Modifier::DIM)), help_area);
+    }
+}
+
+/// Rows whose package name matches `query` with `--filter` semantics
+/// (any of the comma-separated substrings); an empty query matches all.
+fn filter_rows(rows: &[DisplayRow], query: &str) -> Vec<usize> {
+    let matchers: Vec<NameMatcher> = query
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .filter_map(|p| NameMatcher::new(p, false).ok())
+        .collect();
+    rows.iter()
+        .enumerate()
+        .filter(|(_, row)| matchers.is_empty() || matchers.iter().any(|m| m.matches(&row.0.name)))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+fn worst_status(info_list: Option<&Vec<DexOptInfo>>) -> Option<&str> {
+    info_list?.iter().min_by_key(|i| UI::status_score(&i.status)).map(|i| i.status.as_str())
+}
+
+/// Mirrors [`Theme::paint`] for the TUI.
+fn status_style(theme: Theme, status: &str) -> Style {
+    match theme.status_color(status) {
+        Some(color) => Style::new().fg(match color {
+            colored::Color::Green => Color::Green,
+            colored::Color::Yellow => Color::Yellow,
+            colored::Color::Blue => Color::Blue,
+            colored::Color::Red => Color::Red,
+            colored::Color::Magenta => Color::Magenta,
+            colored::Color::Cyan => Color::Cyan,
+            colored::Color::Black => Color::Black,
+            colored::Color::BrightBlack => Color::DarkGray,
+            colored::Color::BrightRed => Color::LightRed,
+            colored::Color::BrightGreen => Color::LightGreen,
+            colored::Color::BrightYellow => Color::LightYellow,
+            colored::Color::BrightBlue => Color::LightBlue,
+            colored::Color::BrightMagenta => Color::LightMagenta,
+            colored::Color::BrightCyan => Color::LightCyan,
+            colored::Color::BrightWhite => Color::White,
+            colored::Color::TrueColor { r, g, b } => Color::Rgb(r, g, b),
+            _ => Color::Reset,
+        }),
+        None => match Theme::mono_style(status) {
+            MonoStyle::Bold => Style::new().add_modifier(Modifier::BOLD),
+            MonoStyle::Underline => Style::new().add_modifier(Modifier::UNDERLINED),
+            MonoStyle::Plain => Style::new(),
+        },
+    }
+}
+
+fn detail_text(row: &DisplayRow, theme: Theme) -> Text<'static> {
+    let (pkg, label, info_list) = row;
+    let field = |name: &str, value: String| {
+        Line::from(vec![Span::styled(format!("{:<8}", name), Style::new().fg(Color::Cyan)), Span::raw(value)])
+    };
+    let mut lines = vec![
+        field("Package", pkg.name.clone()),
+        field("Label", label.clone().unwrap_or_else(|| "(unresolved)".to_string())),
+        field("Path", pkg.path.clone()),
+        Line::default(),
+    ];
+    match info_list {
+        Some(infos) => {
+            for info in infos.iter() {
+                let mut spans = vec![Span::styled(info.raw_line.clone(), status_style(theme, &info.status))];
+                if info.is_secondary {
+                    spans.push(Span::styled("  (secondary dex)", Style::new().add_modifier(Modifier::DIM)));
+                }
+                lines.push(Line::from(spans));
+                if let Some(ref compiled_at) = info.compiled_at {
+                    lines.push(Line::styled(format!("  Last optimized: {}", compiled_at), Style::new().add_modifier(Modifier::DIM)));
+                }
+            }
+        }
+        None => lines.push(Line::styled("(no dexopt data)", Style::new().fg(Color::Red))),
+    }
+    Text::from(lines)
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && !app.handle_key(key.code)
+        {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs the browser until the user quits, restoring the terminal afterwards
+/// (also on panic).
+pub fn run(rows: &[DisplayRow], theme: Theme) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut App::new(rows, theme));
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzer;
+    use crate::package::Package;
+
+    #[test]
+    fn test_tui_filter_and_navigation() {
+        let analyzer = Analyzer::new("[com.google.maps]\n  arm64: [status=verify]\n  arm: [status=speed]\n");
+        let pkgs: Vec<Package> = ["com.android.chrome", "com.google.maps", "org.fdroid"]
+            .iter()
+            .map(|n| Package::new(*n, ""))
+            .collect();
+        let rows: Vec<DisplayRow> = pkgs.iter().map(|p| (p, None, analyzer.get_info(&p.name))).collect();
+
+        assert_eq!(filter_rows(&rows, ""), [0, 1, 2]);
+        assert_eq!(filter_rows(&rows, "google, fdroid"), [1, 2]);
+        assert!(filter_rows(&rows, "nothing").is_empty());
+        assert_eq!(worst_status(rows[1].2), Some("verify"));
+
+        let mut app = App::new(&rows, Theme::Default);
+        assert!(app.handle_key(KeyCode::Char('/')));
+        for c in "com.".chars() {
+            app.handle_key(KeyCode::Char(c));
+        }
+        assert_eq!(app.visible, [0, 1]);
+        // 'q' is text while searching, not quit
+        assert!(app.handle_key(KeyCode::Char('q')));
+        assert!(app.visible.is_empty());
+        app.handle_key(KeyCode::Backspace);
+        app.handle_key(KeyCode::Enter);
+        app.handle_key(KeyCode::End);
+        assert_eq!(app.selected().map(|r| r.0.name.as_str()), Some("com.google.maps"));
+        assert!(!app.handle_key(KeyCode::Char('q')));
+    }
+}
@@ -0,0 +1,1868 @@
+//! Terminal rendering: tables, boxed entries and the summary panel.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, IsTerminal, Write};
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use colored::*;
+use serde::Serialize;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use terminal_size::{Width, terminal_size};
+use unicode_width::UnicodeWidthStr;
+
+use crate::analyzer::{self, recommended_filter, Abi, Deviation, DexOptInfo, Dimension, MissingArtifact, Reconciliation, StatusChange, StatusDiff};
+use crate::error::DexoptError;
+use crate::package::{compile_command, AppType, CompileOutcome, Package};
+use crate::status::{Severity, Status};
+
+pub use crate::analyzer::DisplayRow;
+
+/// Package count and breakdown for one app type, for `--type all` summaries.
+#[derive(Debug, Default, Serialize)]
+pub struct TypeSummary {
+    pub total_apps: usize,
+    pub profiles: BTreeMap<String, usize>,
+}
+
+/// Totals shown in the summary box.
+pub struct Summary<'a> {
+    pub total_apps: usize,
+    pub missing_data: usize,
+    /// Apps with at least one `error` entry.
+    pub errors: usize,
+    pub stats: &'a BTreeMap<String, usize>,
+    pub app_type: AppType,
+    pub summary_by: Dimension,
+    /// Per-type totals, shown when `app_type` is [`AppType::All`].
+    pub by_type: &'a BTreeMap<AppType, TypeSummary>,
+    /// Combined artifact size of the listed packages (`--show-sizes`).
+    pub artifact_bytes: Option<u64>,
+    /// Apps per set of ISAs they have entries for, keyed by [`UI::abi_coverage`].
+    pub abi_coverage: &'a BTreeMap<String, usize>,
+    /// Entries per compilation reason (`unknown` without one), shown as its
+    /// own section when `summary_by` is [`Dimension::Status`].
+    pub reasons: &'a BTreeMap<String, usize>,
+    pub order: SummaryOrder,
+}
+
+/// Releases items tagged with their position in order, holding back any that
+/// arrive before their predecessors (rows streamed from parallel workers).
+pub struct OrderedBuffer<T> {
+    next: usize,
+    pending: BTreeMap<usize, T>,
+}
+
+impl<T> Default for OrderedBuffer<T> {
+    fn default() -> Self {
+        OrderedBuffer { next: 0, pending: BTreeMap::new() }
+    }
+}
+
+impl<T> OrderedBuffer<T> {
+    /// Adds item number `index` and returns every item now in sequence.
+    pub fn push(&mut self, index: usize, item: T) -> Vec<T> {
+        self.pending.insert(index, item);
+        let mut ready = Vec::new();
+        while let Some(item) = self.pending.remove(&self.next) {
+            ready.push(item);
+            self.next += 1;
+        }
+        ready
+    }
+}
+
+/// Width of the ABI column in table mode; fits `riscv64`.
+pub const ABI_COLUMN_WIDTH: usize = 7;
+
+/// Label column of the summary box; longer keys are cut to fit.
+const LABEL_WIDTH: usize = 22;
+
+/// Palette for dexopt statuses (`--theme`).
+#[derive(Copy, Clone, PartialEq, Eq, Default, ValueEnum, Debug)]
+pub enum Theme {
+    /// Green for optimized through red for interpreted
+    #[default]
+    Default,
+    /// Blue and orange at distinct brightness, avoiding the red/green pairing
+    Colorblind,
+    /// No hue: bold for optimized, underline for interpreted
+    Mono,
+}
+
+/// Order of the summary breakdown lines (`--sort-summary`).
+#[derive(Copy, Clone, PartialEq, Eq, Default, ValueEnum, Debug)]
+pub enum SummaryOrder {
+    /// Alphabetically
+    #[default]
+    Name,
+    /// Most entries first
+    Count,
+    /// Least optimized status first
+    Severity,
+}
+
+/// How [`Theme::Mono`] sets a status apart without color.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub(crate) enum MonoStyle {
+    Bold,
+    Underline,
+    Plain,
+}
+
+/// `--color STATUS=COLOR` overrides, installed once at startup.
+static COLOR_OVERRIDES: OnceLock<HashMap<Status, Color>> = OnceLock::new();
+
+impl Theme {
+    // Okabe-Ito colors, distinguishable under the common color vision deficiencies
+    const BLUE: Color = Color::TrueColor { r: 0, g: 114, b: 178 };
+    const SKY_BLUE: Color = Color::TrueColor { r: 86, g: 180, b: 233 };
+    const ORANGE: Color = Color::TrueColor { r: 230, g: 159, b: 0 };
+    const VERMILLION: Color = Color::TrueColor { r: 213, g: 94, b: 0 };
+    const PURPLE: Color = Color::TrueColor { r: 204, g: 121, b: 167 };
+
+    /// Installs the `--color` overrides, which every theme (`mono` included)
+    /// consults before its own palette. Only the first call has an effect.
+    pub fn set_overrides(overrides: HashMap<Status, Color>) {
+        let _ = COLOR_OVERRIDES.set(overrides);
+    }
+
+    /// Parses a `--color` value: a status and one of `colored`'s color names
+    /// (`bright_` / `bright-` prefixes allowed) or a `#rrggbb` hex code,
+    /// e.g. `verify=cyan`, `speed=bright_green`, `error=#ff8800`.
+    pub fn parse_override(spec: &str) -> Result<(Status, Color), DexoptError> {
+        let error = |reason: &str| DexoptError::ParseError { what: "color override", input: spec.to_string(), reason: reason.to_string() };
+        let (status, color) = spec.split_once('=').ok_or_else(|| error("expected STATUS=COLOR"))?;
+        let status = status.parse::<Status>().map_err(|_| error("not a known dexopt status"))?;
+        let color = color.trim().replace(['_', '-'], " ").parse::<Color>().map_err(|_| {
+            error("not a color; use black, red, green, yellow, blue, magenta, cyan, white, their bright_ variants, or #rrggbb")
+        })?;
+        Ok((status, color))
+    }
+
+    /// Hue for `status`, or `None` for themes without color.
+    pub fn status_color(self, status: &str) -> Option<Color> {
+        self.palette_color(status, COLOR_OVERRIDES.get())
+    }
+
+    fn palette_color(self, status: &str, overrides: Option<&HashMap<Status, Color>>) -> Option<Color> {
+        let status = Status::classify(status);
+        if let Some(&color) = overrides.and_then(|o| o.get(&status)) {
+            return Some(color);
+        }
+        let color = match self {
+            Theme::Default => status.color(),
+            Theme::Colorblind => match status {
+                Status::SpeedProfile | Status::Speed => Self::BLUE,
+                Status::Verify => Self::ORANGE,
+                Status::Quicken => Self::SKY_BLUE,
+                Status::RunFromApk | Status::Error => Self::VERMILLION,
+                Status::Everything => Self::PURPLE,
+                _ => Color::White,
+            },
+            Theme::Mono => return None,
+        };
+        Some(color)
+    }
+
+    pub(crate) fn mono_style(status: &str) -> MonoStyle {
+        match Status::classify(status) {
+            Status::Unknown => MonoStyle::Plain,
+            status => match status.severity() {
+                Severity::Optimized => MonoStyle::Bold,
+                Severity::Unoptimized | Severity::Error => MonoStyle::Underline,
+                Severity::Partial => MonoStyle::Plain,
+            },
+        }
+    }
+
+    /// Paints `text` the way this theme shows `status`.
+    pub fn paint(self, text: &str, status: &str) -> ColoredString {
+        match self.status_color(status) {
+            Some(color) => text.color(color),
+            None => match Self::mono_style(status) {
+                MonoStyle::Bold => text.bold(),
+                MonoStyle::Underline => text.underline(),
+                MonoStyle::Plain => text.normal(),
+            },
+        }
+    }
+}
+
+/// Characters for one box outline.
+pub struct BoxChars {
+    pub top_left: &'static str,
+    pub top_right: &'static str,
+    pub bottom_left: &'static str,
+    pub bottom_right: &'static str,
+    /// `╠`/`╣`: a horizontal divider meeting the sides.
+    pub tee_left: &'static str,
+    pub tee_right: &'static str,
+    pub horizontal: &'static str,
+    pub vertical: &'static str,
+}
+
+/// Drawing characters for package boxes (`thin`), the summary box
+/// (`double`) and histogram bars. Every glyph is one column wide in both
+/// sets, so the layout math is shared.
+pub struct Glyphs {
+    pub thin: BoxChars,
+    pub double: BoxChars,
+    pub bar: &'static str,
+}
+
+impl Glyphs {
+    pub const UNICODE: Glyphs = Glyphs {
+        thin: BoxChars {
+            top_left: "┌",
+            top_right: "┐",
+            bottom_left: "└",
+            bottom_right: "┘",
+            tee_left: "├",
+            tee_right: "┤",
+            horizontal: "─",
+            vertical: "│",
+        },
+        double: BoxChars {
+            top_left: "╔",
+            top_right: "╗",
+            bottom_left: "╚",
+            bottom_right: "╝",
+            tee_left: "╠",
+            tee_right: "╣",
+            horizontal: "═",
+            vertical: "║",
+        },
+        bar: "█",
+    };
+
+    /// For terminals without box-drawing glyphs (`--ascii`).
+    pub const ASCII: Glyphs = Glyphs {
+        thin: BoxChars {
+            top_left: "+",
+            top_right: "+",
+            bottom_left: "+",
+            bottom_right: "+",
+            tee_left: "+",
+            tee_right: "+",
+            horizontal: "-",
+            vertical: "|",
+        },
+        double: BoxChars {
+            top_left: "+",
+            top_right: "+",
+            bottom_left: "+",
+            bottom_right: "+",
+            tee_left: "+",
+            tee_right: "+",
+            horizontal: "=",
+            vertical: "|",
+        },
+        bar: "#",
+    };
+
+    /// ASCII when forced, or when the environment suggests the terminal
+    /// can't show Unicode (see [`Glyphs::unicode_likely`]).
+    pub fn select(force_ascii: bool) -> &'static Glyphs {
+        let var = |name| std::env::var(name).ok().filter(|v: &String| !v.is_empty());
+        // The first locale variable that is set wins, as in setlocale(3)
+        let locale = var("LC_ALL").or_else(|| var("LC_CTYPE")).or_else(|| var("LANG"));
+        if force_ascii || !Self::unicode_likely(var("TERM").as_deref(), locale.as_deref()) {
+            &Self::ASCII
+        } else {
+            &Self::UNICODE
+        }
+    }
+
+    /// A dumb terminal or a non-UTF-8 locale means no box drawing. An unset
+    /// locale doesn't: Android shells usually have none and render Unicode fine.
+    fn unicode_likely(term: Option<&str>, locale: Option<&str>) -> bool {
+        if term == Some("dumb") {
+            return false;
+        }
+        locale.is_none_or(|l| {
+            let l = l.to_lowercase();
+            l.contains("utf-8") || l.contains("utf8")
+        })
+    }
+}
+
+impl BoxChars {
+    /// `left` + `width` horizontals + `right`.
+    fn rule(&self, left: &str, right: &str, width: usize) -> String {
+        format!("{}{}{}", left, self.horizontal.repeat(width), right)
+    }
+
+    fn top(&self, width: usize) -> String {
+        self.rule(self.top_left, self.top_right, width)
+    }
+
+    fn divider(&self, width: usize) -> String {
+        self.rule(self.tee_left, self.tee_right, width)
+    }
+
+    fn bottom(&self, width: usize) -> String {
+        self.rule(self.bottom_left, self.bottom_right, width)
+    }
+}
+
+/// Terminal rendering helpers.
+pub struct UI;
+
+impl UI {
+    /// [`Status::score`] of a raw status string; unrecognised ones rank as `unknown`.
+    pub fn status_score(status: &str) -> i32 {
+        Status::classify(status).score()
+    }
+
+    /// Scores a package by its least optimized entry; see [`analyzer::package_score`].
+    pub fn package_score(info_list: Option<&Vec<DexOptInfo>>) -> i32 {
+        analyzer::package_score(info_list)
+    }
+
+    /// Score of the most optimized entry, for `--min-status`.
+    pub fn best_score(infos: &[DexOptInfo]) -> i32 {
+        infos.iter().map(|i| Self::status_score(&i.status)).max().unwrap_or(-2)
+    }
+
+    /// Names the ISAs `infos` has entries for: `arm64 only`, `arm64 + arm`, ...
+    /// `None` when no entry names a known ISA.
+    pub fn abi_coverage(infos: &[DexOptInfo]) -> Option<String> {
+        let abis: Vec<&str> = Abi::ALL
+            .iter()
+            .filter(|abi| infos.iter().any(|i| i.abi == **abi))
+            .filter_map(|abi| abi.isa())
+            .collect();
+        match abis.as_slice() {
+            [] => None,
+            [only] => Some(format!("{} only", only)),
+            _ => Some(abis.join(" + ")),
+        }
+    }
+
+    /// Whether none of the `abi` entries is compiled ahead of time, which
+    /// includes having no `abi` entry at all.
+    pub fn lacks_optimization(infos: &[DexOptInfo], abi: Abi) -> bool {
+        !infos.iter().any(|i| i.abi == abi && i.status_kind().severity() == Severity::Optimized)
+    }
+
+    /// Colors the `status=`/`filter=` token of a dexopt line, leaving the ABI
+    /// and reason in the default foreground. Lines whose token is missing or
+    /// was cut off by truncation are colored as a whole.
+    pub fn colorize_line(line: &str, status: &str, theme: Theme) -> String {
+        let paint = |text: &str| {
+            let painted = theme.paint(text, status);
+            if Status::classify(status) == Status::Error { painted.bold().to_string() } else { painted.to_string() }
+        };
+        let token = ["status=", "filter="].iter().find_map(|key| {
+            let token = format!("{}{}", key, status);
+            line.find(&token).map(|start| start..start + token.len())
+        });
+        match token {
+            Some(span) => format!("{}{}{}", &line[..span.start], paint(&line[span.clone()]), &line[span.end..]),
+            None => paint(line),
+        }
+    }
+
+    /// Removes ANSI SGR sequences (`ESC [ ... m`), leaving the visible text.
+    pub fn strip_ansi(text: &str) -> String {
+        let mut plain = String::with_capacity(text.len());
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                // Parameters run up to the final letter, always `m` for colors
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
+            } else {
+                plain.push(c);
+            }
+        }
+        plain
+    }
+
+    /// Terminal columns `text` occupies once printed: colors take none and
+    /// wide characters take two.
+    pub fn visible_width(text: &str) -> usize {
+        Self::strip_ansi(text).width()
+    }
+
+    /// Joins table cells with ` | `, padding each cell that has an entry in
+    /// `widths` to that many visible columns. Cells may be colored already;
+    /// `format!("{:<N}")` would count their escape codes and wide characters
+    /// as one column each and push the following columns out of line.
+    pub fn table_row(cells: &[String], widths: &[usize]) -> String {
+        cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| match widths.get(i) {
+                Some(&width) => format!("{}{}", cell, " ".repeat(width.saturating_sub(Self::visible_width(cell)))),
+                None => cell.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(" | ")
+    }
+
+    pub fn terminal_width() -> Option<usize> {
+        terminal_size().map(|(Width(w), _)| w as usize)
+    }
+
+    /// Unicode-aware truncation to `max` terminal columns, ending in "..." when cut.
+    pub fn truncate_to_width(s: &str, max: usize) -> String {
+        if s.width() <= max {
+            return s.to_string();
+        }
+        let mut truncated = String::new();
+        let mut w = 0usize;
+        for c in s.chars() {
+            let cw = UnicodeWidthStr::width(c.to_string().as_str());
+            if w + cw > max.saturating_sub(3) {
+                truncated.push_str("...");
+                break;
+            }
+            truncated.push(c);
+            w += cw;
+        }
+        truncated
+    }
+
+    /// Width of the package column in table mode: as wide as the longest
+    /// name, but shrunk so the ABI and status columns still fit the terminal.
+    pub fn package_column_width(longest_name: usize) -> usize {
+        const DEFAULT: usize = 45;
+        const MIN: usize = 20;
+        // " | " + ABI + " | " + room for a short status
+        const RESERVED: usize = 3 + ABI_COLUMN_WIDTH + 3 + 30;
+
+        match Self::terminal_width() {
+            Some(w) => longest_name.min(w.saturating_sub(RESERVED)).max(MIN),
+            None => DEFAULT,
+        }
+    }
+
+    /// Package column text in table mode: the label is shown alongside the
+    /// name when it has been resolved.
+    pub fn table_name(pkg: &Package, app_label: Option<&str>) -> String {
+        match app_label {
+            Some(label) => format!("{} ({})", label, pkg.name),
+            None => pkg.name.clone(),
+        }
+    }
+
+    /// `--sort label` order: by label (case-insensitively), then package
+    /// name, with packages whose label couldn't be resolved after the rest.
+    pub fn compare_by_label(a: &DisplayRow, b: &DisplayRow) -> Ordering {
+        let key = |row: &DisplayRow| (row.1.is_none(), row.1.as_deref().map(str::to_lowercase));
+        key(a).cmp(&key(b)).then_with(|| a.0.name.cmp(&b.0.name))
+    }
+
+    /// Progress bar on stderr for the parallel label resolution; hidden when
+    /// `enabled` is false so it never interleaves with piped output.
+    pub fn label_progress(len: usize, enabled: bool) -> ProgressBar {
+        Self::progress(len, enabled, "Resolving labels")
+    }
+
+    /// Progress of a `--compile` batch; the message names the last package done.
+    pub fn compile_progress(len: usize, enabled: bool) -> ProgressBar {
+        Self::progress(len, enabled, "Compiling")
+    }
+
+    fn progress(len: usize, enabled: bool, action: &str) -> ProgressBar {
+        if !enabled {
+            return ProgressBar::hidden();
+        }
+        let bar = ProgressBar::with_draw_target(Some(len as u64), ProgressDrawTarget::stderr());
+        let template = format!("{{spinner:.cyan}} {} [{{bar:30.cyan/blue}}] {{pos}}/{{len}} {{wide_msg}}", action);
+        bar.set_style(
+            ProgressStyle::with_template(&template)
+                .expect("Invalid progress template")
+                .progress_chars("=> "),
+        );
+        bar
+    }
+
+    pub fn print_header(out: &mut dyn Write, pkg_width: usize) -> io::Result<()> {
+        let cells = [("Package", pkg_width), ("ABI", ABI_COLUMN_WIDTH), ("DexOpt Status", 30)]
+            .map(|(title, width)| Self::pad_to_width(title, width).bold().underline().to_string());
+        writeln!(out, "\n{}\n", Self::table_row(&cells, &[pkg_width, ABI_COLUMN_WIDTH]))
+    }
+
+    /// Boxed per-package view for `--verbose`. `artifact_bytes` is
+    /// `Some(None)` when sizes were asked for but no artifacts were found.
+    pub fn print_block_entry(
+        stdout: &mut dyn Write,
+        row: &DisplayRow,
+        artifact_bytes: Option<Option<u64>>,
+        recommend: bool,
+        theme: Theme,
+        glyphs: &Glyphs,
+    ) -> io::Result<()> {
+        let &(pkg, ref app_label, info_list) = row;
+        let app_label = app_label.as_deref();
+        let min_width: usize = 40;
+        let b = &glyphs.thin;
+
+        let max_term_width = Self::terminal_width().map_or(120, |w| w.saturating_sub(4));
+
+        // Build plain (no ANSI) display name for accurate width measurement
+        let full_display_name = Self::table_name(pkg, app_label);
+
+        let display_name = Self::truncate_to_width(&full_display_name, max_term_width);
+
+        // content_width = visual width of the plain text (no ANSI)
+        let content_width = display_name.width();
+        // box_width = number of ─ chars; actual rendered line is │ + ─*box_width + │
+        let box_width = (content_width + 4).max(min_width).min(max_term_width);
+
+        writeln!(stdout, "{}", b.top(box_width).cyan())?;
+
+        // Padding: p_l + content_width + p_r == box_width (inner space between │ │)
+        let p_space = box_width.saturating_sub(content_width);
+        let p_l = p_space / 2;
+        let p_r = p_space - p_l;
+
+        // Build colorized version — ANSI bytes don't affect terminal column positions
+        let inner_content = if display_name.ends_with("...") {
+            display_name.bold().bright_white().to_string()
+        } else {
+            match app_label {
+                Some(_) => {
+                    let pkg_suffix = format!(" ({})", pkg.name);
+                    if display_name.ends_with(&pkg_suffix) {
+                        let label_part = &display_name[..display_name.len() - pkg_suffix.len()];
+                        format!(
+                            "{} ({})",
+                            label_part.bold().cyan(),
+                            pkg.name.bold().bright_white()
+                        )
+                    } else {
+                        // Suffix was truncated — colour whole string
+                        display_name.bold().bright_white().to_string()
+                    }
+                }
+                None => display_name.bold().bright_white().to_string(),
+            }
+        };
+
+        writeln!(
+            stdout,
+            "{}{}{}{}",
+            b.vertical.cyan(),
+            " ".repeat(p_l),
+            inner_content,
+            format!("{}{}", " ".repeat(p_r), b.vertical).cyan()
+        )?;
+
+        writeln!(stdout, "{}", b.bottom(box_width).cyan())?;
+        if !pkg.enabled {
+            writeln!(stdout, "  {} {}", "State:".dimmed(), "disabled".yellow().bold())?;
+        }
+        if let Some(bytes) = artifact_bytes {
+            let size = bytes.map_or_else(|| "none found".to_string(), Self::format_size);
+            writeln!(stdout, "  {} {}", "Artifacts:".dimmed(), size)?;
+        }
+
+        if let Some(infos) = info_list {
+            let files = Self::file_statuses(infos);
+            if files.len() > 1 {
+                let files: Vec<String> =
+                    files.iter().map(|(file, status)| format!("{}: {}", file, theme.paint(status, status))).collect();
+                writeln!(stdout, "  {} {}", "Dex files:".dimmed(), files.join(" / "))?;
+            }
+
+            let max_prefix_len = infos
+                .iter()
+                .filter_map(|i| i.raw_line.find(':'))
+                .max()
+                .unwrap_or(0);
+
+            let (primary, secondary): (Vec<&DexOptInfo>, Vec<&DexOptInfo>) =
+                infos.iter().partition(|i| !i.is_secondary);
+            let groups = if secondary.is_empty() {
+                vec![(None, primary)]
+            } else {
+                vec![(Some("Primary dex"), primary), (Some("Secondary dex"), secondary)]
+            };
+
+            for (title, entries) in groups {
+                let indent = if let Some(title) = title {
+                    writeln!(stdout, "  {}", title.dimmed().bold())?;
+                    "    "
+                } else {
+                    "  "
+                };
+                for info in entries {
+                    let suggestion = if recommend { Self::recommendation(pkg, info) } else { None };
+                    Self::print_info_line(stdout, info, indent, max_prefix_len, max_term_width, suggestion, theme)?;
+                }
+            }
+        } else {
+            writeln!(stdout, "  {}", "(no dexopt data)".italic().red())?;
+        }
+        writeln!(stdout)?;
+        Ok(())
+    }
+
+    /// Each dex file / APK the entries refer to, in dump order, with the
+    /// least optimized status reported for it.
+    pub fn file_statuses(infos: &[DexOptInfo]) -> Vec<(&str, &str)> {
+        let mut files: Vec<(&str, &str)> = Vec::new();
+        for info in infos {
+            let Some(file) = info.file_name() else {
+                continue;
+            };
+            match files.iter_mut().find(|(f, _)| *f == file) {
+                Some(entry) if Self::status_score(&info.status) < Self::status_score(entry.1) => entry.1 = &info.status,
+                Some(_) => {}
+                None => files.push((file, &info.status)),
+            }
+        }
+        files
+    }
+
+    /// `--raw`: the package's dump block exactly as `dumpsys` printed it.
+    pub fn print_raw_block(stdout: &mut dyn Write, lines: Option<&[String]>) -> io::Result<()> {
+        writeln!(stdout, "{}", "--- dumpsys block ---".dimmed())?;
+        match lines {
+            Some(lines) => {
+                for line in lines {
+                    writeln!(stdout, "{}", line)?;
+                }
+            }
+            None => writeln!(stdout, "{}", "(not in the dump)".italic().dimmed())?,
+        }
+        writeln!(stdout)
+    }
+
+    /// The filter `info` should be on per [`recommended_filter`].
+    pub fn recommendation(pkg: &Package, info: &DexOptInfo) -> Option<&'static str> {
+        recommended_filter(pkg.app_type, info.reason.as_deref(), &info.status)
+    }
+
+    /// The advisory shown after a status line under `--compiler-filter-recommendation`.
+    pub fn suggestion_note(filter: &str) -> ColoredString {
+        format!("(suggest {})", filter).cyan()
+    }
+
+    pub fn print_info_line(
+        stdout: &mut dyn Write,
+        info: &DexOptInfo,
+        indent: &str,
+        max_prefix_len: usize,
+        max_term_width: usize,
+        suggestion: Option<&str>,
+        theme: Theme,
+    ) -> io::Result<()> {
+        let raw_line = if info.raw_line.width() > max_term_width {
+            let mut s = info.raw_line.chars().take(max_term_width - 3).collect::<String>();
+            s.push_str("...");
+            s
+        } else {
+            info.raw_line.clone()
+        };
+
+        let formatted = if let Some(idx) = raw_line.find(':') {
+            let (prefix, rest) = raw_line.split_at(idx);
+            format!("{:width$}{}", prefix, rest, width = max_prefix_len)
+        } else {
+            raw_line
+        };
+        write!(stdout, "{}{}", indent, Self::colorize_line(&formatted, &info.status, theme))?;
+        if info.version_mismatch
+            && let Some(ref version) = info.version
+        {
+            write!(stdout, " {}", format!("(stale version {})", version).yellow())?;
+        }
+        if let Some(filter) = suggestion {
+            write!(stdout, " {}", Self::suggestion_note(filter))?;
+        }
+        writeln!(stdout)?;
+        if let Some(ref compiled_at) = info.compiled_at {
+            writeln!(stdout, "{}  {}", indent, format!("Last optimized: {}", compiled_at).dimmed())?;
+        }
+        Ok(())
+    }
+
+    /// Prints one section per distinct status (or reason), listing the packages in it.
+    pub fn print_groups(stdout: &mut dyn Write, rows: &[DisplayRow], dim: Dimension, theme: Theme) -> io::Result<()> {
+        const NO_DATA: &str = "(no dexopt data)";
+        let mut groups: BTreeMap<&str, Vec<&Package>> = BTreeMap::new();
+        for (pkg, _, info_list) in rows {
+            match info_list {
+                Some(infos) => {
+                    let mut keys: Vec<&str> = infos.iter().map(|i| i.dimension_value(dim)).collect();
+                    keys.sort_unstable();
+                    keys.dedup();
+                    for key in keys {
+                        groups.entry(key).or_default().push(pkg);
+                    }
+                }
+                None => groups.entry(NO_DATA).or_default().push(pkg),
+            }
+        }
+
+        let mut ordered: Vec<(&str, Vec<&Package>)> = groups.into_iter().collect();
+        if dim == Dimension::Status {
+            // Least optimized first; packages without data lead
+            let score = |key: &str| if key == NO_DATA { i32::MIN } else { Self::status_score(key) };
+            ordered.sort_by(|a, b| score(a.0).cmp(&score(b.0)).then_with(|| a.0.cmp(b.0)));
+        }
+
+        for (key, mut pkgs) in ordered {
+            pkgs.sort_by(|a, b| a.name.cmp(&b.name));
+            let header = format!("{} ({})", key, pkgs.len());
+            writeln!(stdout, "\n{}", theme.paint(&header, key).bold().underline())?;
+            for pkg in pkgs {
+                writeln!(stdout, "  {}", pkg.name.bright_white())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn print_diff(stdout: &mut dyn Write, diff: &StatusDiff) -> io::Result<()> {
+        writeln!(stdout, "\n{}\n", "Status changes since baseline".bold().underline())?;
+
+        let mut changes: Vec<(&StatusChange, Color)> = diff
+            .improved
+            .iter()
+            .map(|c| (c, Color::Green))
+            .chain(diff.regressed.iter().map(|c| (c, Color::Red)))
+            .chain(diff.changed.iter().map(|c| (c, Color::Yellow)))
+            .collect();
+        changes.sort_by(|a, b| a.0.package.cmp(b.0.package));
+
+        if changes.is_empty() {
+            writeln!(stdout, "  {}", "(no status changes)".italic().dimmed())?;
+        }
+        for (change, color) in changes {
+            let transition = format!(
+                "{} -> {}",
+                change.before.as_deref().unwrap_or("(none)"),
+                change.after.as_deref().unwrap_or("(none)")
+            );
+            writeln!(stdout, "  {}: {}", change.package.bright_white(), transition.color(color))?;
+        }
+
+        writeln!(
+            stdout,
+            "\n{} improved, {} regressed, {} changed at the same rank, {} unchanged",
+            diff.improved.len().to_string().green().bold(),
+            diff.regressed.len().to_string().red().bold(),
+            diff.changed.len().to_string().yellow().bold(),
+            diff.unchanged.len().to_string().bold()
+        )
+    }
+
+    /// The `--reconcile` report: one warning section per kind of mismatch
+    /// between the package list and the dump.
+    pub fn print_reconciliation(stdout: &mut dyn Write, rec: &Reconciliation) -> io::Result<()> {
+        let sections = [
+            ("Orphaned dexopt data (in the dump, not installed)", &rec.orphaned),
+            ("No dexopt data (installed, not in the dump)", &rec.no_data),
+        ];
+        for (title, names) in sections {
+            let heading = format!("{}: {}", title, names.len());
+            writeln!(stdout, "\n{}\n", if names.is_empty() { heading.bold() } else { heading.yellow().bold() })?;
+            if names.is_empty() {
+                writeln!(stdout, "  {}", "(none)".italic().dimmed())?;
+            }
+            for name in names.iter() {
+                writeln!(stdout, "  {}", name.bright_white())?;
+            }
+        }
+        if !rec.orphaned.is_empty() || !rec.no_data.is_empty() {
+            writeln!(stdout, "\n{} The package list and the dump were probably captured at different times.", "Hint:".yellow().bold())?;
+        }
+        Ok(())
+    }
+
+    /// The `--verify-artifacts` warnings: entries whose status says compiled
+    /// but whose oat file is gone.
+    pub fn print_missing_artifacts(stdout: &mut dyn Write, missing: &[MissingArtifact], theme: Theme) -> io::Result<()> {
+        let heading = format!("Compiled status without artifacts: {}", missing.len());
+        if missing.is_empty() {
+            writeln!(stdout, "\n{}\n", heading.bold())?;
+            return writeln!(stdout, "  {}", "(every compiled entry has its oat file)".italic().dimmed());
+        }
+        writeln!(stdout, "\n{}\n", format!("Warning: {}", heading).yellow().bold())?;
+        let pkg_width = missing.iter().map(|m| m.package.width()).max().unwrap_or(0);
+        for m in missing {
+            writeln!(
+                stdout,
+                "  {} {} {}",
+                Self::pad_to_width(m.package, pkg_width).bright_white(),
+                format!("{:<ABI_COLUMN_WIDTH$}", m.abi).dimmed(),
+                theme.paint(m.status, m.status)
+            )?;
+        }
+        writeln!(
+            stdout,
+            "\n{} The recorded status is stale, so --compile counts these as already optimized; force a recompile with: cmd package compile -f -m <filter> <package>",
+            "Hint:".yellow().bold()
+        )
+    }
+
+    /// The `--compare-to-default` report: one row per entry whose filter
+    /// isn't the one its reason should produce.
+    pub fn print_deviations(stdout: &mut dyn Write, deviations: &[Deviation], from_device: bool, theme: Theme) -> io::Result<()> {
+        writeln!(stdout, "\n{}\n", "Deviations from default compiler filters".bold().underline())?;
+
+        if deviations.is_empty() {
+            writeln!(stdout, "  {}", "(every entry matches its default)".italic().dimmed())?;
+        } else {
+            let pkg_width = deviations.iter().map(|d| d.package.width()).max().unwrap_or(0).max("Package".len());
+            let reason_width = deviations.iter().map(|d| d.reason.width()).max().unwrap_or(0).max("Reason".len());
+            let status_width = deviations.iter().map(|d| d.status.width()).max().unwrap_or(0).max("Current".len());
+            writeln!(
+                stdout,
+                "  {} | {:<ABI_COLUMN_WIDTH$} | {} | {} | {}",
+                Self::pad_to_width("Package", pkg_width).bold(),
+                "ABI".bold(),
+                Self::pad_to_width("Reason", reason_width).bold(),
+                Self::pad_to_width("Current", status_width).bold(),
+                "Expected".bold()
+            )?;
+            for d in deviations {
+                writeln!(
+                    stdout,
+                    "  {} | {} | {} | {} | {}",
+                    Self::pad_to_width(d.package, pkg_width).bright_white(),
+                    format!("{:<ABI_COLUMN_WIDTH$}", d.abi).dimmed(),
+                    Self::pad_to_width(d.reason, reason_width),
+                    theme.paint(&Self::pad_to_width(d.status, status_width), d.status),
+                    theme.paint(&d.expected, &d.expected)
+                )?;
+            }
+        }
+
+        let source = if from_device { "device pm.dexopt.* properties" } else { "AOSP defaults" };
+        writeln!(
+            stdout,
+            "\n{} deviating entr{} (expected filters from {})",
+            deviations.len().to_string().bold(),
+            if deviations.len() == 1 { "y" } else { "ies" },
+            source
+        )
+    }
+
+    /// Whether any of a package's entries failed to dexopt.
+    pub fn has_error(info_list: Option<&Vec<DexOptInfo>>) -> bool {
+        info_list.is_some_and(|infos| infos.iter().any(|i| i.status_kind() == Status::Error))
+    }
+
+    /// The `--count-abis` matrix below the summary: entries per status (rows,
+    /// in `order`) and ABI (columns, 64-bit first), with row totals.
+    pub fn print_abi_matrix(
+        stdout: &mut dyn Write,
+        matrix: &BTreeMap<String, BTreeMap<Abi, usize>>,
+        order: SummaryOrder,
+        theme: Theme,
+    ) -> io::Result<()> {
+        writeln!(stdout, "\n{}\n", "Entries by status and ABI".bold().underline())?;
+        if matrix.is_empty() {
+            return writeln!(stdout, "  {}", "(no dexopt data)".italic().dimmed());
+        }
+
+        let mut abis: Vec<Abi> = matrix.values().flat_map(|row| row.keys().copied()).collect();
+        abis.sort_unstable();
+        abis.dedup();
+        let totals: BTreeMap<String, usize> = matrix.iter().map(|(status, row)| (status.clone(), row.values().sum())).collect();
+        let status_width = matrix.keys().map(|s| s.width()).max().unwrap_or(0).max("Status".len());
+        let column_width = totals.values().map(|n| n.to_string().len()).max().unwrap_or(0).max(ABI_COLUMN_WIDTH);
+
+        let mut header = format!("  {}", Self::pad_to_width("Status", status_width));
+        for abi in abis.iter().map(ToString::to_string).chain(["Total".to_string()]) {
+            header.push_str(&format!("  {:>column_width$}", abi));
+        }
+        writeln!(stdout, "{}", header.bold())?;
+        for (status, total) in Self::ordered_stats(&totals, order) {
+            let row = &matrix[status];
+            let mut line = format!("  {}", theme.paint(&Self::pad_to_width(status, status_width), status));
+            for abi in &abis {
+                match row.get(abi) {
+                    Some(n) => line.push_str(&format!("  {:>column_width$}", n)),
+                    None => line.push_str(&format!("  {}", format!("{:>column_width$}", "-").dimmed())),
+                }
+            }
+            line.push_str(&format!("  {:>column_width$}", total));
+            writeln!(stdout, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    /// The `--explain` legend: what each status in `rows` means, least
+    /// optimized first. Prints nothing when no package has dexopt data.
+    pub fn print_legend(stdout: &mut dyn Write, rows: &[DisplayRow], theme: Theme) -> io::Result<()> {
+        let mut statuses: Vec<&str> = rows.iter().flat_map(|row| row.2.into_iter().flatten()).map(|i| i.status.as_str()).collect();
+        statuses.sort_by(|a, b| Self::status_score(a).cmp(&Self::status_score(b)).then_with(|| a.cmp(b)));
+        statuses.dedup();
+        if statuses.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(stdout, "\n{}\n", "Status legend".bold().underline())?;
+        let width = statuses.iter().map(|s| s.width()).max().unwrap_or(0);
+        for status in statuses {
+            writeln!(
+                stdout,
+                "  {}  {}",
+                theme.paint(&Self::pad_to_width(status, width), status),
+                Status::classify(status).description()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Lists every `error` entry with its raw dump line, so failed or corrupt
+    /// dexopt state isn't buried in the table. Prints nothing when none failed.
+    pub fn print_errors(stdout: &mut dyn Write, rows: &[DisplayRow]) -> io::Result<()> {
+        let failed: Vec<(&str, &DexOptInfo)> = rows
+            .iter()
+            .flat_map(|(pkg, _, info_list)| {
+                info_list
+                    .iter()
+                    .copied()
+                    .flatten()
+                    .filter(|i| i.status_kind() == Status::Error)
+                    .map(|i| (pkg.name.as_str(), i))
+            })
+            .collect();
+        if failed.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(stdout, "\n{}\n", "Errors".red().bold())?;
+        for (name, info) in &failed {
+            writeln!(stdout, "  {} {}", name.bright_white(), info.raw_line.red())?;
+            if let Some(ref path) = info.path {
+                writeln!(stdout, "    {}", path.dimmed())?;
+            }
+        }
+        writeln!(
+            stdout,
+            "\n{} failed entr{}. Recompile with: dexter --compile speed-profile -p <package>",
+            failed.len().to_string().red().bold(),
+            if failed.len() == 1 { "y" } else { "ies" }
+        )
+    }
+
+    /// Lists entries that have a profile but still run from the APK or are only
+    /// verified, with the command that fixes them. Prints nothing when none do.
+    pub fn print_optimizable(stdout: &mut dyn Write, rows: &[DisplayRow], theme: Theme) -> io::Result<()> {
+        let flagged: Vec<(&str, &DexOptInfo)> = rows
+            .iter()
+            .flat_map(|(pkg, _, info_list)| {
+                info_list.iter().copied().flatten().filter(|i| i.is_optimizable()).map(|i| (pkg.name.as_str(), i))
+            })
+            .collect();
+        if flagged.is_empty() {
+            return Ok(());
+        }
+
+        writeln!(stdout, "\n{}\n", "Optimizable packages (profile present, not compiled with it)".yellow().bold())?;
+        for (name, info) in &flagged {
+            writeln!(
+                stdout,
+                "  {} {} {}",
+                name.bright_white(),
+                format!("{:<ABI_COLUMN_WIDTH$}", info.abi).dimmed(),
+                theme.paint(&info.status, &info.status)
+            )?;
+        }
+        writeln!(
+            stdout,
+            "\n{} Recompile with: dexter --compile speed-profile -p <package>",
+            "Hint:".yellow().bold()
+        )
+    }
+
+    pub fn print_summary(out: &mut dyn Write, summary: &Summary, theme: Theme, glyphs: &Glyphs) -> io::Result<()> {
+        let Summary { total_apps, missing_data, errors, stats, app_type, summary_by, by_type, artifact_bytes, abi_coverage, reasons, order } =
+            *summary;
+        let width = 47;
+        let b_blue = Color::BrightBlue;
+        let b_yellow = Color::BrightYellow;
+        let b = &glyphs.double;
+
+        writeln!(out, "\n\n{}", b.top(width).color(b_blue))?;
+
+        let title = "DEXOPT ANALYSIS SUMMARY";
+        let (p_s, p_e) = Self::center_padding(title, width);
+        writeln!(
+            out,
+            "{}{}{}{}",
+            b.vertical.color(b_blue),
+            " ".repeat(p_s),
+            title.bold().color(b_yellow),
+            format!("{}{}", " ".repeat(p_e), b.vertical).color(b_blue)
+        )?;
+
+        writeln!(out, "{}", b.divider(width).color(b_blue))?;
+
+        Self::add_summary_line(out, b, "App Scope", &app_type.to_string(), Color::Cyan, Color::Magenta, width)?;
+        Self::add_summary_line(out, b, "Total Apps Checked", &total_apps.to_string(), Color::Cyan, Color::BrightGreen, width)?;
+        let missing_color = if missing_data > 0 { Color::Red } else { Color::BrightGreen };
+        Self::add_summary_line(out, b, "Missing Data", &missing_data.to_string(), Color::Cyan, missing_color, width)?;
+        let error_color = if errors > 0 { Color::Red } else { Color::BrightGreen };
+        Self::add_summary_line(out, b, "Apps With Errors", &errors.to_string(), Color::Cyan, error_color, width)?;
+        if let Some(bytes) = artifact_bytes {
+            Self::add_summary_line(out, b, "Artifact Size", &Self::format_size(bytes), Color::Cyan, Color::BrightYellow, width)?;
+        }
+
+        let sub = match summary_by {
+            Dimension::Status => "Profile Breakdown",
+            Dimension::Reason => "Reason Breakdown",
+        };
+        // Breakdowns count entries (one per ISA and dex file), so their shares
+        // are of all entries; only ABI coverage counts apps
+        let entries = |stats: &BTreeMap<String, usize>| stats.values().sum::<usize>();
+        let rows = Self::ordered_stats(stats, order);
+        let title = format!("{} ({} entries)", sub, entries(stats));
+        Self::print_breakdown(out, b, &title, entries(stats), &rows, width, theme)?;
+        Self::print_histogram(out, glyphs, &rows, width, theme)?;
+
+        // Only a mixed scope has anything to split
+        if app_type == AppType::All {
+            for (kind, summary) in by_type {
+                let title = format!("{} Apps {} ({} entries)", kind, sub, entries(&summary.profiles));
+                let rows = Self::ordered_stats(&summary.profiles, order);
+                Self::print_breakdown(out, b, &title, entries(&summary.profiles), &rows, width, theme)?;
+            }
+        }
+        if summary_by == Dimension::Status && !reasons.is_empty() {
+            let rows = Self::ordered_stats(reasons, order);
+            let title = format!("Reason Breakdown ({} entries)", entries(reasons));
+            Self::print_breakdown(out, b, &title, entries(reasons), &rows, width, theme)?;
+        }
+        if !abi_coverage.is_empty() {
+            let rows = Self::ordered_stats(abi_coverage, order);
+            Self::print_breakdown(out, b, "ABI Coverage", total_apps, &rows, width, theme)?;
+        }
+        writeln!(out, "{}", b.bottom(width).color(b_blue))
+    }
+
+    /// One titled section of the summary box; percentages are of `total`.
+    fn print_breakdown(
+        out: &mut dyn Write,
+        b: &BoxChars,
+        title: &str,
+        total: usize,
+        stats: &[(&str, usize)],
+        width: usize,
+        theme: Theme,
+    ) -> io::Result<()> {
+        let b_blue = Color::BrightBlue;
+        let mid = b.divider(width).color(b_blue);
+        writeln!(out, "{}", mid)?;
+        let (p_s, p_e) = Self::center_padding(title, width);
+        writeln!(
+            out,
+            "{}{}{}{}",
+            b.vertical.color(b_blue),
+            " ".repeat(p_s),
+            title.dimmed().bold(),
+            format!("{}{}", " ".repeat(p_e), b.vertical).color(b_blue)
+        )?;
+        writeln!(out, "{}", mid)?;
+
+        if stats.is_empty() {
+            let msg = "No profile data found.";
+            let padding = " ".repeat(width.saturating_sub(2 + msg.width()));
+            writeln!(out, "{}  {}{}{}", b.vertical.color(b_blue), msg, padding, b.vertical.color(b_blue))?;
+        } else {
+            for (profile, count) in stats {
+                let pct = Self::format_percentage(*count, total);
+                Self::add_breakdown_line(out, b, profile, &count.to_string(), &pct, theme, width)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// One bar per entry, scaled so the largest count fills the space left of the label.
+    fn print_histogram(
+        out: &mut dyn Write,
+        glyphs: &Glyphs,
+        stats: &[(&str, usize)],
+        width: usize,
+        theme: Theme,
+    ) -> io::Result<()> {
+        let Some(max) = stats.iter().map(|(_, count)| *count).max() else {
+            return Ok(());
+        };
+        let b_blue = Color::BrightBlue;
+        let b = &glyphs.double;
+        let bar_space = width.saturating_sub(2 + LABEL_WIDTH + 1 + 2);
+        writeln!(out, "{}", b.divider(width).color(b_blue))?;
+        for (profile, count) in stats {
+            let len = Self::histogram_bar(*count, max, bar_space);
+            let bar = theme.paint(&glyphs.bar.repeat(len), profile);
+            let padding = " ".repeat(bar_space - len + 2);
+            writeln!(
+                out,
+                "{}  {} {}{}{}",
+                b.vertical.color(b_blue),
+                Self::label_cell(profile).dimmed(),
+                bar,
+                padding,
+                b.vertical.color(b_blue)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Breakdown entries in `order`; ties (and equal severities) fall back
+    /// to alphabetical.
+    pub fn ordered_stats(stats: &BTreeMap<String, usize>, order: SummaryOrder) -> Vec<(&str, usize)> {
+        let mut rows: Vec<(&str, usize)> = stats.iter().map(|(key, count)| (key.as_str(), *count)).collect();
+        match order {
+            SummaryOrder::Name => {}
+            SummaryOrder::Count => rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0))),
+            SummaryOrder::Severity => rows.sort_by_key(|(key, _)| Self::status_score(key)),
+        }
+        rows
+    }
+
+    /// Bar length for `count` out of `max` in `space` columns; non-zero counts
+    /// always get at least one block.
+    fn histogram_bar(count: usize, max: usize, space: usize) -> usize {
+        if max == 0 || count == 0 {
+            return 0;
+        }
+        (count * space).div_ceil(max).clamp(1, space)
+    }
+
+    /// Left/right padding that centers `text` in `width` terminal columns.
+    fn center_padding(text: &str, width: usize) -> (usize, usize) {
+        let space = width.saturating_sub(text.width());
+        (space / 2, space - space / 2)
+    }
+
+    /// Left-aligns `text` in `width` terminal columns; `{:<N}` pads by chars,
+    /// which misaligns wide characters.
+    fn pad_to_width(text: &str, width: usize) -> String {
+        format!("{}{}", text, " ".repeat(width.saturating_sub(text.width())))
+    }
+
+    /// `label` truncated and padded to exactly [`LABEL_WIDTH`] columns, so
+    /// long reason or ABI keys can't push the box border out.
+    fn label_cell(label: &str) -> String {
+        Self::pad_to_width(&Self::truncate_to_width(label, LABEL_WIDTH), LABEL_WIDTH)
+    }
+
+    /// Formats a byte count with binary units (`512 B`, `12.3 MiB`).
+    pub fn format_size(bytes: u64) -> String {
+        const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+        if bytes < 1024 {
+            return format!("{} B", bytes);
+        }
+        let mut value = bytes as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+
+    /// Formats `count` as a share of `total` with one decimal place.
+    pub fn format_percentage(count: usize, total: usize) -> String {
+        if total == 0 {
+            return "0.0%".to_string();
+        }
+        format!("{:.1}%", count as f64 * 100.0 / total as f64)
+    }
+
+    /// Like `add_summary_line`, with a percentage right-aligned against the
+    /// border and the value painted in `label`'s status style.
+    fn add_breakdown_line(
+        out: &mut dyn Write,
+        b: &BoxChars,
+        label: &str,
+        value: &str,
+        pct: &str,
+        theme: Theme,
+        width: usize,
+    ) -> io::Result<()> {
+        let l_part = Self::label_cell(label).bold().color(Color::Cyan);
+        let v_part = theme.paint(value, label).bold();
+        let padding = " ".repeat(width.saturating_sub(5 + LABEL_WIDTH + value.width() + pct.width() + 2));
+        writeln!(
+            out,
+            "{}  {} : {}{}{}  {}",
+            b.vertical.color(Color::BrightBlue),
+            l_part,
+            v_part,
+            padding,
+            pct.dimmed(),
+            b.vertical.color(Color::BrightBlue)
+        )
+    }
+
+    /// Lists the commands `--compile` would run, without running them.
+    pub fn print_compile_plan(
+        out: &mut dyn Write,
+        filter: &str,
+        pending: &[&DisplayRow],
+        already_optimized: usize,
+        theme: Theme,
+    ) -> io::Result<()> {
+        writeln!(out, "\n{}\n", format!("Compile plan ({}, dry run)", filter).bold().underline())?;
+
+        for (pkg, _, info_list) in pending {
+            let before = StatusDiff::status_list(*info_list).unwrap_or_else(|| "(none)".to_string());
+            writeln!(out, "{}", compile_command(&pkg.name, filter).bright_white())?;
+            writeln!(out, "    {} -> {}", theme.paint(&before, &before), theme.paint(filter, filter))?;
+        }
+
+        writeln!(
+            out,
+            "\n{} package(s) would be recompiled, {} already optimized",
+            pending.len().to_string().green().bold(),
+            already_optimized.to_string().bold()
+        )
+    }
+
+    /// One line per package, a tally, then each failure with its full stderr.
+    pub fn print_compile_results(out: &mut dyn Write, filter: &str, results: &[(&str, CompileOutcome)]) -> io::Result<()> {
+        writeln!(out, "\n{}\n", format!("Compile results ({})", filter).bold().underline())?;
+
+        let width = Self::package_column_width(results.iter().map(|(name, _)| name.width()).max().unwrap_or(0));
+        let (mut compiled, mut skipped, mut cancelled) = (0, 0, 0);
+        let mut failures = Vec::new();
+        for (name, outcome) in results {
+            let result = match outcome {
+                CompileOutcome::Compiled => {
+                    compiled += 1;
+                    "compiled".green()
+                }
+                CompileOutcome::AlreadyOptimized => {
+                    skipped += 1;
+                    "already optimized".dimmed()
+                }
+                CompileOutcome::Cancelled => {
+                    cancelled += 1;
+                    "not started".yellow()
+                }
+                CompileOutcome::Failed(err) => {
+                    failures.push((name, err));
+                    "failed".red().bold()
+                }
+            };
+            let name_cell = Self::truncate_to_width(name, width).bright_white().to_string();
+            writeln!(out, "{}", Self::table_row(&[name_cell, result.to_string()], &[width]))?;
+        }
+
+        write!(
+            out,
+            "\n{} compiled, {} already optimized, {} failed",
+            compiled.to_string().green().bold(),
+            skipped.to_string().bold(),
+            failures.len().to_string().red().bold()
+        )?;
+        if cancelled > 0 {
+            write!(out, ", {} not started (interrupted)", cancelled.to_string().yellow().bold())?;
+        }
+        writeln!(out)?;
+
+        if !failures.is_empty() {
+            writeln!(out, "\n{}", "Failed compiles".red().bold())?;
+            for (name, err) in failures {
+                writeln!(out, "\n  {}", name.bright_white())?;
+                if err.is_empty() {
+                    writeln!(out, "    {}", "(no error output)".italic().dimmed())?;
+                }
+                for line in err.lines() {
+                    writeln!(out, "    {}", line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn add_summary_line(
+        out: &mut dyn Write,
+        b: &BoxChars,
+        label: &str,
+        value: &str,
+        l_col: Color,
+        v_col: Color,
+        width: usize,
+    ) -> io::Result<()> {
+        let l_part = Self::label_cell(label).bold().color(l_col);
+        let v_part = value.bold().color(v_col);
+        let padding = " ".repeat(width.saturating_sub(5 + LABEL_WIDTH + value.width()));
+        writeln!(
+            out,
+            "{}  {} : {}{}{}",
+            b.vertical.color(Color::BrightBlue),
+            l_part,
+            v_part,
+            padding,
+            b.vertical.color(Color::BrightBlue)
+        )
+    }
+}
+
+/// Quotes a CSV field per RFC 4180 when it contains a delimiter, quote or newline.
+pub fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Serializes a report built for JSON as TOML, which has no null: null
+/// values are left out of tables and arrays.
+pub fn toml_string(report: &serde_json::Value) -> Result<String, toml::ser::Error> {
+    fn strip_nulls(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                map.retain(|_, v| !v.is_null());
+                map.values_mut().for_each(strip_nulls);
+            }
+            serde_json::Value::Array(items) => {
+                items.retain(|v| !v.is_null());
+                items.iter_mut().for_each(strip_nulls);
+            }
+            _ => {}
+        }
+    }
+    let mut report = report.clone();
+    strip_nulls(&mut report);
+    toml::to_string(&report)
+}
+
+/// Formats the `--stats-line` summary: `apps=N entries=M` followed by one
+/// `key=count` pair per breakdown entry, space-separated and never colored.
+/// The pairs count entries, so they add up to `entries`, not `apps`.
+pub fn stats_line(apps: usize, stats: &BTreeMap<String, usize>) -> String {
+    let mut line = format!("apps={} entries={}", apps, stats.values().sum::<usize>());
+    for (key, count) in stats {
+        line.push_str(&format!(" {}={}", key, count));
+    }
+    line
+}
+
+/// Decides whether to emit ANSI colors: `--no-color` and `NO_COLOR` always
+/// disable them, otherwise they follow whether stdout is a terminal unless
+/// `CLICOLOR_FORCE` is set.
+pub fn color_enabled(no_color_flag: bool) -> bool {
+    let env_set = |key: &str| std::env::var_os(key).is_some_and(|v| !v.is_empty() && v != "0");
+    if no_color_flag || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+        return false;
+    }
+    env_set("CLICOLOR_FORCE") || io::stdout().is_terminal()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::Analyzer;
+
+    #[test]
+    fn test_status_score_ordering() {
+        let ordered = ["error", "run-from-apk", "verify", "quicken", "speed", "speed-profile"];
+        for pair in ordered.windows(2) {
+            assert!(
+                UI::status_score(pair[0]) < UI::status_score(pair[1]),
+                "{} should rank below {}", pair[0], pair[1]
+            );
+        }
+        assert!(UI::package_score(None) < UI::status_score("error"));
+
+        let analyzer = Analyzer::new("[com.a]\n  arm64: [status=speed-profile]\n  arm: [status=verify]\n");
+        let infos = analyzer.get_info("com.a").unwrap();
+        assert_eq!(UI::package_score(Some(infos)), UI::status_score("verify"));
+        assert_eq!(UI::best_score(infos), UI::status_score("speed-profile"));
+    }
+
+    #[test]
+    fn test_table_columns_align_by_visible_width() {
+        colored::control::set_override(true);
+        let widths = [20, ABI_COLUMN_WIDTH];
+        let rows = [
+            UI::table_row(&["com.a".bright_white().to_string(), "arm64".dimmed().to_string(), "x".to_string()], &widths),
+            UI::table_row(&["日本語 (com.b)".bright_white().to_string(), "x86".dimmed().to_string(), "y".to_string()], &widths),
+            UI::table_row(
+                &[String::new(), "riscv64".to_string(), UI::colorize_line("[status=speed]", "speed", Theme::Default)],
+                &widths,
+            ),
+        ];
+        assert!(rows[0].contains('\x1b'));
+        let status_column = |row: &str| {
+            let plain = UI::strip_ansi(row);
+            let start = plain.rfind(" | ").unwrap() + 3;
+            plain[..start].width()
+        };
+        for row in &rows {
+            assert_eq!(status_column(row), 20 + 3 + ABI_COLUMN_WIDTH + 3, "{:?}", UI::strip_ansi(row));
+        }
+        assert_eq!(UI::strip_ansi(&rows[2]), format!("{:20} | riscv64 | [status=speed]", ""));
+        assert_eq!(UI::visible_width(&"日本".red().to_string()), 4);
+    }
+
+    #[test]
+    fn test_abi_matrix() {
+        let matrix = BTreeMap::from([
+            ("speed-profile".to_string(), BTreeMap::from([(Abi::Arm, 2), (Abi::Arm64, 80)])),
+            ("run-from-apk".to_string(), BTreeMap::from([(Abi::Arm, 3)])),
+        ]);
+        let mut out = Vec::new();
+        UI::print_abi_matrix(&mut out, &matrix, SummaryOrder::Severity, Theme::Default).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        let lines: Vec<&str> = rendered.lines().filter(|l| l.starts_with("  ")).collect();
+        assert_eq!(lines, [
+            "  Status           arm64      arm    Total",
+            "  run-from-apk         -        3        3",
+            "  speed-profile       80        2       82",
+        ]);
+    }
+
+    #[test]
+    fn test_status_legend() {
+        let analyzer = Analyzer::new("[com.a]\n  arm64: [status=speed-profile]\n  arm: [status=verify]\n[com.b]\n  arm64: [status=verify]\n");
+        let pkgs = [Package::new("com.a", ""), Package::new("com.b", ""), Package::new("com.c", "")];
+        let rows: Vec<DisplayRow> = pkgs.iter().map(|p| (p, None, analyzer.get_info(&p.name))).collect();
+
+        let mut out = Vec::new();
+        UI::print_legend(&mut out, &rows, Theme::Default).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        let lines: Vec<&str> = rendered.lines().filter(|l| l.starts_with("  ")).collect();
+        assert_eq!(lines, [
+            format!("  verify         {}", Status::Verify.description()),
+            format!("  speed-profile  {}", Status::SpeedProfile.description()),
+        ]);
+
+        let mut out = Vec::new();
+        UI::print_legend(&mut out, &rows[2..], Theme::Default).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_errors_section() {
+        let analyzer = Analyzer::new(
+            "[com.broken]\n  path: /data/app/b/base.apk\n    arm64: [status=error] [reason=install]\n    arm: [status=speed]\n\
+             [com.fine]\n  arm64: [status=speed-profile]\n",
+        );
+        let pkgs = [Package::new("com.broken", ""), Package::new("com.fine", "")];
+        let rows: Vec<DisplayRow> = pkgs.iter().map(|p| (p, None, analyzer.get_info(&p.name))).collect();
+        assert!(UI::has_error(rows[0].2));
+        assert!(!UI::has_error(rows[1].2));
+        assert!(!UI::has_error(None));
+
+        let mut out = Vec::new();
+        UI::print_errors(&mut out, &rows).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        assert!(rendered.contains("com.broken arm64: [status=error] [reason=install]"), "{}", rendered);
+        assert!(rendered.contains("/data/app/b/base.apk"), "{}", rendered);
+        assert!(!rendered.contains("com.fine"));
+        assert!(rendered.contains("1 failed entry. Recompile with: dexter --compile speed-profile -p <package>"), "{}", rendered);
+
+        let mut out = Vec::new();
+        UI::print_errors(&mut out, &rows[1..]).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_missing_artifacts_section() {
+        let missing = [MissingArtifact { package: "com.stale", abi: Abi::Arm64, status: "speed-profile" }];
+        let mut out = Vec::new();
+        UI::print_missing_artifacts(&mut out, &missing, Theme::Default).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        assert!(rendered.contains("Compiled status without artifacts: 1"), "{}", rendered);
+        assert!(rendered.contains("com.stale arm64"), "{}", rendered);
+        assert!(rendered.contains("cmd package compile -f -m <filter> <package>"), "{}", rendered);
+        assert!(!rendered.contains("dexter --compile"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_optimizable_section() {
+        let analyzer = Analyzer::new(
+            "[com.slow]\n  arm64: [status=verify] [reason=install] [profile-present]\n[com.fine]\n  arm64: [status=speed-profile]\n",
+        );
+        let pkgs = [Package::new("com.slow", ""), Package::new("com.fine", "")];
+        let rows: Vec<DisplayRow> = pkgs.iter().map(|p| (p, None, analyzer.get_info(&p.name))).collect();
+
+        let mut out = Vec::new();
+        UI::print_optimizable(&mut out, &rows, Theme::Default).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        assert!(rendered.contains("com.slow arm64"), "{}", rendered);
+        assert!(!rendered.contains("com.fine"), "{}", rendered);
+        assert!(rendered.contains("Recompile with: dexter --compile speed-profile -p <package>"), "{}", rendered);
+
+        let mut out = Vec::new();
+        UI::print_optimizable(&mut out, &rows[1..], Theme::Default).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_ordered_buffer() {
+        let mut buffer = OrderedBuffer::default();
+        assert!(buffer.push(2, 'c').is_empty());
+        assert!(buffer.push(1, 'b').is_empty());
+        assert_eq!(buffer.push(0, 'a'), ['a', 'b', 'c']);
+        assert_eq!(buffer.push(3, 'd'), ['d']);
+        assert!(buffer.push(5, 'f').is_empty());
+        assert_eq!(buffer.push(4, 'e'), ['e', 'f']);
+    }
+
+    #[test]
+    fn test_compare_by_label() {
+        let pkgs: Vec<Package> = ["com.z", "com.y", "com.b", "com.a"].iter().map(|n| Package::new(*n, "")).collect();
+        let labels = [Some("alpha"), None, Some("Beta"), None];
+        let mut rows: Vec<DisplayRow> = pkgs.iter().zip(labels).map(|(p, l)| (p, l.map(String::from), None)).collect();
+        rows.sort_by(UI::compare_by_label);
+        let names: Vec<&str> = rows.iter().map(|row| row.0.name.as_str()).collect();
+        assert_eq!(names, ["com.z", "com.b", "com.a", "com.y"]);
+    }
+
+    #[test]
+    fn test_abi_coverage() {
+        let analyzer = Analyzer::new(
+            "[com.both]\n  arm: [status=verify]\n  arm64: [status=speed-profile]\n\
+             [com.arm]\n  arm: [status=speed]\n",
+        );
+        let both = analyzer.get_info("com.both").unwrap();
+        let arm = analyzer.get_info("com.arm").unwrap();
+        assert_eq!(UI::abi_coverage(both).as_deref(), Some("arm64 + arm"));
+        assert_eq!(UI::abi_coverage(arm).as_deref(), Some("arm only"));
+        assert_eq!(UI::abi_coverage(&[]), None);
+
+        assert!(UI::lacks_optimization(both, Abi::Arm));
+        assert!(!UI::lacks_optimization(both, Abi::Arm64));
+        // No arm64 code at all counts as lacking it
+        assert!(UI::lacks_optimization(arm, Abi::Arm64));
+        assert!(!UI::lacks_optimization(arm, Abi::Arm));
+    }
+
+    #[test]
+    fn test_colorize_status_token_only() {
+        colored::control::set_override(true);
+        let line = "arm64: [status=speed] [reason=cmdline]";
+        let colored = UI::colorize_line(line, "speed", Theme::Default);
+        assert!(colored.starts_with("arm64: [\x1b["), "{:?}", colored);
+        assert!(colored.ends_with("] [reason=cmdline]"), "{:?}", colored);
+        assert!(colored.contains(&"status=speed".green().to_string()));
+
+        let error = UI::colorize_line("arm64: [filter=error]", "error", Theme::Default);
+        assert!(error.contains(&"filter=error".red().bold().to_string()));
+
+        // Truncated lines without the token fall back to whole-line color
+        assert_eq!(UI::colorize_line("arm64: [stat...", "verify", Theme::Default), "arm64: [stat...".yellow().to_string());
+    }
+
+    #[test]
+    fn test_theme_palettes() {
+        colored::control::set_override(true);
+        // The colorblind palette never pairs red with green
+        for status in ["speed-profile", "speed", "run-from-apk", "error"] {
+            let color = Theme::Colorblind.status_color(status).unwrap();
+            assert!(!matches!(color, Color::Red | Color::Green), "{}: {:?}", status, color);
+        }
+        assert_ne!(Theme::Colorblind.status_color("speed"), Theme::Colorblind.status_color("run-from-apk"));
+
+        assert_eq!(Theme::Mono.status_color("speed"), None);
+        assert_eq!(Theme::Mono.paint("ok", "speed").to_string(), "ok".bold().to_string());
+        assert_eq!(Theme::Mono.paint("bad", "run-from-apk").to_string(), "bad".underline().to_string());
+        assert_eq!(Theme::Mono.paint("meh", "verify").to_string(), "meh");
+        let line = UI::colorize_line("arm64: [status=verify]", "verify", Theme::Mono);
+        assert!(!line.contains("\x1b[3"), "{:?}", line);
+
+        assert_eq!(Theme::parse_override("verify=cyan").unwrap(), (Status::Verify, Color::Cyan));
+        assert_eq!(Theme::parse_override("Speed=bright_green").unwrap(), (Status::Speed, Color::BrightGreen));
+        assert_eq!(Theme::parse_override("error=#ff8800").unwrap().1, Color::TrueColor { r: 255, g: 136, b: 0 });
+        for bad in ["verify", "fast=red", "verify=chartreuse"] {
+            assert!(matches!(Theme::parse_override(bad), Err(DexoptError::ParseError { what: "color override", .. })), "{}", bad);
+        }
+        let overrides = HashMap::from([(Status::Verify, Color::Cyan)]);
+        assert_eq!(Theme::Mono.palette_color("verify", Some(&overrides)), Some(Color::Cyan));
+        assert_eq!(Theme::Default.palette_color("VERIFY", Some(&overrides)), Some(Color::Cyan));
+        assert_eq!(Theme::Default.palette_color("speed", Some(&overrides)), Some(Color::Green));
+    }
+
+    #[test]
+    fn test_truncate_to_width() {
+        assert_eq!(UI::truncate_to_width("com.example", 20), "com.example");
+        assert_eq!(UI::truncate_to_width("com.example.verylongname", 10), "com.exa...");
+        // Wide characters count as two columns
+        let cut = UI::truncate_to_width("日本語のアプリ名前", 9);
+        assert!(cut.width() <= 9, "{:?} is {} columns", cut, cut.width());
+        assert!(cut.ends_with("..."));
+    }
+
+    #[test]
+    fn test_histogram_bar_scaling() {
+        assert_eq!(UI::histogram_bar(10, 10, 20), 20);
+        assert_eq!(UI::histogram_bar(5, 10, 20), 10);
+        assert_eq!(UI::histogram_bar(1, 1000, 20), 1);
+        assert_eq!(UI::histogram_bar(0, 10, 20), 0);
+        assert_eq!(UI::histogram_bar(0, 0, 20), 0);
+    }
+
+    #[test]
+    fn test_summary_order() {
+        let stats = BTreeMap::from([
+            ("speed-profile".to_string(), 5),
+            ("verify".to_string(), 9),
+            ("run-from-apk".to_string(), 5),
+            ("speed".to_string(), 1),
+        ]);
+        let keys = |order| UI::ordered_stats(&stats, order).into_iter().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys(SummaryOrder::Name), ["run-from-apk", "speed", "speed-profile", "verify"]);
+        assert_eq!(keys(SummaryOrder::Count), ["verify", "run-from-apk", "speed-profile", "speed"]);
+        assert_eq!(keys(SummaryOrder::Severity), ["run-from-apk", "verify", "speed", "speed-profile"]);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(UI::format_size(0), "0 B");
+        assert_eq!(UI::format_size(1023), "1023 B");
+        assert_eq!(UI::format_size(1536), "1.5 KiB");
+        assert_eq!(UI::format_size(12 << 20), "12.0 MiB");
+        assert_eq!(UI::format_size(5 << 30), "5.0 GiB");
+    }
+
+    #[test]
+    fn test_format_percentage() {
+        assert_eq!(UI::format_percentage(1, 3), "33.3%");
+        assert_eq!(UI::format_percentage(80, 80), "100.0%");
+        assert_eq!(UI::format_percentage(5, 0), "0.0%");
+    }
+
+    #[test]
+    fn test_toml_report() {
+        let report = serde_json::json!({
+            "package": [
+                { "package": "com.a", "label": null, "dexopt_info": [{ "abi": "arm64", "status": "speed", "reason": null }] },
+                { "package": "com.b", "label": "B", "dexopt_info": null },
+            ],
+            "summary": { "total_apps": 2, "profiles": { "speed": 1 }, "api_level": null },
+        });
+        let toml = toml_string(&report).unwrap();
+        assert!(toml.contains("[[package]]\npackage = \"com.a\"\n"), "{}", toml);
+        assert!(toml.contains("[[package.dexopt_info]]\nabi = \"arm64\"\nstatus = \"speed\"\n"), "{}", toml);
+        assert!(toml.contains("[summary]\ntotal_apps = 2\n"), "{}", toml);
+        assert!(!toml.contains("null") && !toml.contains("api_level") && !toml.contains("reason"));
+        // It parses back to the same data, minus the nulls
+        let parsed: toml::Table = toml.parse().unwrap();
+        assert_eq!(parsed["package"][1]["label"].as_str(), Some("B"));
+        assert_eq!(parsed["summary"]["profiles"]["speed"].as_integer(), Some(1));
+    }
+
+    #[test]
+    fn test_csv_escape() {
+        assert_eq!(csv_escape("com.example.app"), "com.example.app");
+        assert_eq!(csv_escape("Foo, Bar"), "\"Foo, Bar\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_escape(""), "");
+    }
+
+    #[test]
+    fn test_stats_line() {
+        let stats = BTreeMap::from([("speed-profile".to_string(), 80), ("verify".to_string(), 30)]);
+        assert_eq!(stats_line(100, &stats), "apps=100 entries=110 speed-profile=80 verify=30");
+        assert_eq!(stats_line(0, &BTreeMap::new()), "apps=0 entries=0");
+    }
+
+    #[test]
+    fn test_file_statuses() {
+        let dump = "[com.a]\n  path: /data/app/a/base.apk\n    arm64: [status=speed-profile]\n    arm: [status=verify]\n  \
+                    path: /data/app/a/split_config.arm64_v8a.apk\n    arm64: [status=speed]\n";
+        let analyzer = Analyzer::new(dump);
+        let infos = analyzer.get_info("com.a").unwrap();
+        assert_eq!(UI::file_statuses(infos), [("base.apk", "verify"), ("split_config.arm64_v8a.apk", "speed")]);
+
+        let analyzer = Analyzer::new("[com.b]\n  arm64: [status=speed]\n");
+        assert!(UI::file_statuses(analyzer.get_info("com.b").unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_box_padding_is_exact() {
+        // p_l + content_width + p_r must equal box_width exactly
+        for content_len in [5, 20, 40, 60, 80] {
+            let min_width: usize = 40;
+            let max_term_width: usize = 120;
+            let box_width = (content_len + 4).max(min_width).min(max_term_width);
+            let p_space = box_width.saturating_sub(content_len);
+            let p_l = p_space / 2;
+            let p_r = p_space - p_l;
+            assert_eq!(p_l + content_len + p_r, box_width,
+                "Padding mismatch for content_len={}", content_len);
+        }
+    }
+
+    #[test]
+    fn test_box_borders_align_with_wide_labels() {
+        let pkg = Package::new("com.example.chat", "");
+        for label in ["微信", "カメラ📷", "Plain"] {
+            let mut out = Vec::new();
+            let row: DisplayRow = (&pkg, Some(label.to_string()), None);
+            UI::print_block_entry(&mut out, &row, None, false, Theme::Default, &Glyphs::UNICODE).unwrap();
+            let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+            let lines: Vec<&str> = rendered.lines().take(3).collect();
+            let top = lines[0].width();
+            assert!(lines[1].ends_with('│'), "{:?}", lines[1]);
+            assert_eq!(lines[1].width(), top, "content row misaligned for {:?}", label);
+            assert_eq!(lines[2].width(), top, "bottom border misaligned for {:?}", label);
+        }
+        assert_eq!(UI::center_padding("日本", 10), (3, 3));
+        assert_eq!(UI::pad_to_width("日本", 6).width(), 6);
+    }
+
+    #[test]
+    fn test_breakdown_shares_are_of_entries() {
+        // One app has an entry per ISA, so entries outnumber apps
+        let analyzer = Analyzer::new(
+            "[com.multi]\n  arm64: [status=speed-profile] [reason=bg-dexopt]\n  arm: [status=speed-profile] [reason=bg-dexopt]\n  \
+             x86_64: [status=speed-profile] [reason=bg-dexopt]\n[com.one]\n  arm64: [status=verify] [reason=install]\n",
+        );
+        let mut stats = BTreeMap::new();
+        for name in ["com.multi", "com.one"] {
+            for info in analyzer.get_info(name).unwrap() {
+                *stats.entry(info.dimension_value(Dimension::Reason).to_string()).or_insert(0) += 1;
+            }
+        }
+        let summary = Summary {
+            total_apps: 2,
+            missing_data: 0,
+            errors: 0,
+            stats: &stats,
+            app_type: AppType::User,
+            summary_by: Dimension::Reason,
+            by_type: &BTreeMap::new(),
+            artifact_bytes: None,
+            abi_coverage: &BTreeMap::from([("arm64 only".to_string(), 1), ("arm64 + arm + x86_64".to_string(), 1)]),
+            reasons: &stats,
+            order: SummaryOrder::Name,
+        };
+        let mut out = Vec::new();
+        UI::print_summary(&mut out, &summary, Theme::Default, &Glyphs::UNICODE).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        assert!(rendered.contains("Reason Breakdown (4 entries)"), "{}", rendered);
+        assert!(rendered.contains("bg-dexopt              : 3            75.0%"), "{}", rendered);
+        assert!(rendered.contains("install                : 1            25.0%"), "{}", rendered);
+        assert!(!rendered.contains("150.0%"), "{}", rendered);
+        // ABI coverage still counts apps
+        assert!(rendered.contains("arm64 only             : 1            50.0%"), "{}", rendered);
+    }
+
+    #[test]
+    fn test_long_breakdown_keys_are_truncated() {
+        let stats = BTreeMap::from([("speed-profile".to_string(), 2)]);
+        let reasons = BTreeMap::from([
+            ("boot-after-mainline-update".to_string(), 1),
+            ("install-bulk-secondary-downgraded".to_string(), 1),
+        ]);
+        let summary = Summary {
+            total_apps: 2,
+            missing_data: 0,
+            errors: 0,
+            stats: &stats,
+            app_type: AppType::User,
+            summary_by: Dimension::Status,
+            by_type: &BTreeMap::new(),
+            artifact_bytes: None,
+            abi_coverage: &BTreeMap::new(),
+            reasons: &reasons,
+            order: SummaryOrder::Name,
+        };
+        let mut out = Vec::new();
+        UI::print_summary(&mut out, &summary, Theme::Default, &Glyphs::UNICODE).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        let line = |prefix: &str| rendered.lines().find(|l| l.contains(prefix)).unwrap_or_else(|| panic!("{}", rendered)).to_string();
+        // 47 columns between the borders
+        for long in [line("boot-after-mainline..."), line("install-bulk-second...")] {
+            assert_eq!(long.width(), 47 + 2, "{}", long);
+        }
+    }
+
+    #[test]
+    fn test_long_histogram_keys_keep_box_width() {
+        // --summary-by reason draws the histogram over reason keys
+        let stats = BTreeMap::from([
+            ("boot-after-mainline-update".to_string(), 3),
+            ("install-bulk-secondary-downgraded".to_string(), 1),
+            ("install".to_string(), 2),
+        ]);
+        let summary = Summary {
+            total_apps: 6,
+            missing_data: 0,
+            errors: 0,
+            stats: &stats,
+            app_type: AppType::User,
+            summary_by: Dimension::Reason,
+            by_type: &BTreeMap::new(),
+            artifact_bytes: None,
+            abi_coverage: &BTreeMap::from([("arm64 + arm + x86_64 + riscv64".to_string(), 6)]),
+            reasons: &stats,
+            order: SummaryOrder::Count,
+        };
+        let mut out = Vec::new();
+        UI::print_summary(&mut out, &summary, Theme::Default, &Glyphs::UNICODE).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        let lines: Vec<&str> = rendered.lines().filter(|l| !l.is_empty()).collect();
+        assert!(lines.iter().any(|l| l.contains("boot-after-mainline... █")), "{}", rendered);
+        // 47 columns between the borders on every line, histogram included
+        for line in &lines {
+            assert_eq!(line.width(), 47 + 2, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_compile_results_align_long_names() {
+        let long = "com.example.an.unusually.long.package.name.for.alignment";
+        let results = [
+            ("com.a", CompileOutcome::Compiled),
+            (long, CompileOutcome::Failed("boom".to_string())),
+            ("com.b", CompileOutcome::AlreadyOptimized),
+        ];
+        let mut out = Vec::new();
+        UI::print_compile_results(&mut out, "speed", &results).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        let columns: Vec<usize> = rendered.lines().filter_map(|l| l.find(" | ")).collect();
+        assert_eq!(columns.len(), 3, "{}", rendered);
+        assert!(columns.iter().all(|&c| c == columns[0]), "{}", rendered);
+        // The failure section still names the package in full
+        assert!(rendered.lines().any(|l| l.contains(long) && !l.contains(" | ")), "{}", rendered);
+    }
+
+    #[test]
+    fn test_ascii_glyphs_keep_alignment() {
+        let stats = BTreeMap::from([("speed".to_string(), 3), ("verify".to_string(), 1)]);
+        let summary = Summary {
+            total_apps: 4,
+            missing_data: 0,
+            errors: 1,
+            stats: &stats,
+            app_type: AppType::User,
+            summary_by: Dimension::Status,
+            by_type: &BTreeMap::new(),
+            artifact_bytes: Some(3 << 20),
+            abi_coverage: &BTreeMap::from([("arm64 only".to_string(), 3), ("arm64 + arm".to_string(), 1)]),
+            reasons: &BTreeMap::from([("ab-ota".to_string(), 3), ("unknown".to_string(), 1)]),
+            order: SummaryOrder::Name,
+        };
+        let mut out = Vec::new();
+        UI::print_summary(&mut out, &summary, Theme::Default, &Glyphs::ASCII).unwrap();
+        let rendered = UI::strip_ansi(&String::from_utf8(out).unwrap());
+        let lines: Vec<&str> = rendered.lines().filter(|l| !l.is_empty()).collect();
+        assert!(rendered.is_ascii(), "{}", rendered);
+        assert!(lines.iter().all(|l| l.len() == lines[0].len()), "{}", rendered);
+        assert!(rendered.contains("###"));
+        assert!(rendered.contains("Artifact Size          : 3.0 MiB"), "{}", rendered);
+        assert!(rendered.contains("Apps With Errors       : 1"), "{}", rendered);
+        assert!(rendered.contains("ABI Coverage"), "{}", rendered);
+        assert!(rendered.contains("arm64 + arm"), "{}", rendered);
+        assert!(rendered.contains("Reason Breakdown"), "{}", rendered);
+        assert!(rendered.contains("ab-ota"), "{}", rendered);
+
+        assert!(Glyphs::unicode_likely(None, None));
+        assert!(Glyphs::unicode_likely(Some("xterm-256color"), Some("en_US.UTF-8")));
+        assert!(!Glyphs::unicode_likely(Some("dumb"), None));
+        assert!(!Glyphs::unicode_likely(Some("xterm"), Some("C")));
+    }
+}
@@ -0,0 +1,184 @@
+//! Fixture-driven tests for the `dumpsys package dexopt` parser.
+
+use dexter::{Abi, Analyzer};
+
+fn fixture(name: &str) -> String {
+    let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("missing fixture {}: {}", path, e))
+}
+
+fn statuses(analyzer: &Analyzer, pkg: &str) -> Vec<String> {
+    analyzer
+        .get_info(pkg)
+        .unwrap_or_else(|| panic!("{} was not parsed", pkg))
+        .iter()
+        .map(|i| i.status.clone())
+        .collect()
+}
+
+#[test]
+fn test_parses_multi_abi_blocks() {
+    let analyzer = Analyzer::new(&fixture("android14_dexopt.txt"));
+
+    assert_eq!(statuses(&analyzer, "com.example.app"), ["speed-profile", "verify"]);
+    let infos = analyzer.get_info("com.example.app").unwrap();
+    assert_eq!(infos[0].abi, Abi::Arm64);
+    assert_eq!(infos[1].abi, Abi::Arm);
+    assert_eq!(infos[0].reason.as_deref(), Some("bg-dexopt"));
+    assert_eq!(infos[1].reason.as_deref(), Some("install"));
+
+    assert_eq!(statuses(&analyzer, "com.example.noabi"), ["run-from-apk"]);
+    assert_eq!(statuses(&analyzer, "com.android.settings"), ["speed"]);
+    // "Dexopt state:" and "Dexopt artifacts:" are section titles, not packages
+    assert!(analyzer.get_info("Dexopt state:").is_none());
+}
+
+#[test]
+fn test_accepts_filter_and_status_keys() {
+    let analyzer = Analyzer::new(&fixture("legacy_filter.txt"));
+
+    assert_eq!(statuses(&analyzer, "com.legacy.app"), ["quicken", "quicken"]);
+    assert_eq!(statuses(&analyzer, "com.legacy.mixed"), ["verify"]);
+    assert_eq!(
+        analyzer.get_info("com.legacy.mixed").unwrap()[0].reason.as_deref(),
+        Some("ab-ota")
+    );
+}
+
+#[test]
+fn test_packages_without_status_lines_have_no_info() {
+    let analyzer = Analyzer::new(&fixture("legacy_filter.txt"));
+    assert!(analyzer.get_info("com.legacy.nodata").is_none());
+}
+
+#[test]
+fn test_truncated_dump_degrades_gracefully() {
+    let dump = fixture("truncated.txt");
+    let analyzer = Analyzer::new(&dump);
+
+    // The cut-off entry is kept with whatever status text survived
+    assert_eq!(statuses(&analyzer, "com.cut.app"), ["speed-prof"]);
+    // An unterminated header never opens a package block
+    assert!(analyzer.get_info("com.broken").is_none());
+    assert!(!analyzer.looks_truncated(&dump, 1));
+}
+
+#[test]
+fn test_empty_dump_is_reported_as_truncated() {
+    let analyzer = Analyzer::new("");
+    assert!(analyzer.get_info("com.example.app").is_none());
+    assert!(analyzer.looks_truncated("", 3));
+}
+
+#[test]
+fn test_parses_emulator_isas() {
+    let analyzer = Analyzer::new(&fixture("emulator_x86_64.txt"));
+
+    assert_eq!(statuses(&analyzer, "com.example.emu"), ["speed-profile", "verify"]);
+    let infos = analyzer.get_info("com.example.emu").unwrap();
+    assert_eq!(infos[0].abi, Abi::X86_64);
+    assert_eq!(infos[1].abi, Abi::X86);
+    assert_eq!(infos[0].details(), "[status=speed-profile] [reason=bg-dexopt] [primary-abi]");
+
+    assert_eq!(statuses(&analyzer, "com.android.emu.settings"), ["speed"]);
+    assert_eq!(analyzer.get_info("com.example.riscv").unwrap()[0].abi, Abi::Riscv64);
+}
+
+#[test]
+fn test_parses_android11_layout() {
+    let analyzer = Analyzer::new(&fixture("android11_dexopt.txt"));
+
+    assert_eq!(statuses(&analyzer, "com.google.android.youtube"), ["speed-profile", "quicken"]);
+    assert_eq!(statuses(&analyzer, "com.whatsapp"), ["verify", "run-from-apk"]);
+    let secondary: Vec<bool> = analyzer.get_info("com.whatsapp").unwrap().iter().map(|i| i.is_secondary).collect();
+    assert_eq!(secondary, [false, true]);
+    // Single-segment package names are still packages
+    assert_eq!(statuses(&analyzer, "android"), ["speed"]);
+}
+
+#[test]
+fn test_parses_android12_splits_and_trailer() {
+    let analyzer = Analyzer::new(&fixture("android12_dexopt.txt"));
+
+    assert_eq!(statuses(&analyzer, "com.google.android.gms"), ["speed-profile", "speed-profile"]);
+    // Lines under the trailing "Dexopt artifacts:" section belong to no package
+    assert_eq!(statuses(&analyzer, "com.android.vending"), ["verify"]);
+}
+
+#[test]
+fn test_entries_record_their_dex_path() {
+    let analyzer = Analyzer::new(&fixture("android12_dexopt.txt"));
+    let files: Vec<Option<&str>> =
+        analyzer.get_info("com.google.android.gms").unwrap().iter().map(|i| i.file_name()).collect();
+    assert_eq!(files, [Some("base.apk"), Some("split_config.arm64_v8a.apk")]);
+
+    let analyzer = Analyzer::new(&fixture("android14_art_service.txt"));
+    let infos = analyzer.get_info("com.google.android.apps.maps").unwrap();
+    // Both ISAs of the APK share its path; the secondary dex has its own
+    assert_eq!(infos[1].path, infos[0].path);
+    assert_eq!(infos[2].path.as_deref(), Some("/data/user/0/com.google.android.apps.maps/app_dex/offline.dex"));
+
+    // Blocks without path lines leave it unset
+    let analyzer = Analyzer::new("[com.a]\n  arm64: [status=speed]\n");
+    assert_eq!(analyzer.get_info("com.a").unwrap()[0].path, None);
+}
+
+#[test]
+fn test_parses_headers_with_metadata() {
+    let analyzer = Analyzer::new(&fixture("android13_vendor_rom.txt"));
+
+    assert_eq!(statuses(&analyzer, "com.vendor.gallery"), ["speed-profile"]);
+    assert_eq!(statuses(&analyzer, "com.vendor.launcher"), ["speed"]);
+    assert_eq!(statuses(&analyzer, "com.example.plain"), ["verify"]);
+    // A bracketed token inside a block doesn't open a new one
+    assert!(analyzer.get_info("primary-abi").is_none());
+}
+
+#[test]
+fn test_bracketed_tokens_at_header_level_stay_in_block() {
+    let analyzer = Analyzer::new(&fixture("shared_library_brackets.txt"));
+
+    assert_eq!(statuses(&analyzer, "com.google.android.trichromelibrary_602312033"), ["speed-profile"]);
+    assert_eq!(statuses(&analyzer, "com.android.chrome"), ["speed-profile", "verify"]);
+    assert!(analyzer.get_info("libraries").is_none());
+    assert!(analyzer.get_info("shared library").is_none());
+    assert_eq!(statuses(&analyzer, "android"), ["speed"]);
+}
+
+#[test]
+fn test_raw_blocks_are_kept_verbatim() {
+    let analyzer = Analyzer::new(&fixture("android14_art_service.txt"));
+
+    let block = analyzer.raw_block("com.google.android.apps.maps").unwrap();
+    assert_eq!(block[0], "  [com.google.android.apps.maps]");
+    assert_eq!(block.len(), 11, "every line up to the next header: {:#?}", block);
+    assert_eq!(block.last().unwrap().trim(), "class loader context: PCL[]");
+    assert!(block.iter().any(|l| l.contains("[location is")));
+    assert_eq!(analyzer.raw_block("com.android.systemui").unwrap().len(), 4);
+    assert!(analyzer.raw_block("com.missing").is_none());
+
+    // The trailing section isn't part of the last block
+    let analyzer = Analyzer::new(&fixture("android12_dexopt.txt"));
+    assert!(!analyzer.raw_block("com.android.vending").unwrap().iter().any(|l| l.contains("[reason=boot]")));
+}
+
+#[test]
+fn test_parses_android14_art_service_layout() {
+    let analyzer = Analyzer::new(&fixture("android14_art_service.txt"));
+
+    let infos = analyzer.get_info("com.google.android.apps.maps").unwrap();
+    let parsed: Vec<(&str, bool)> = infos.iter().map(|i| (i.status.as_str(), i.is_secondary)).collect();
+    assert_eq!(parsed, [("speed-profile", false), ("verify", false), ("verify", true)]);
+    assert_eq!(statuses(&analyzer, "com.android.systemui"), ["speed"]);
+}
+
+#[test]
+fn test_single_package_parse_skips_other_blocks() {
+    let dump = fixture("android14_dexopt.txt");
+    let analyzer = Analyzer::for_package(&dump, "com.example.noabi");
+
+    assert_eq!(statuses(&analyzer, "com.example.noabi"), ["run-from-apk"]);
+    assert!(analyzer.get_info("com.example.app").is_none());
+    assert!(analyzer.get_info("com.android.settings").is_none());
+    assert!(Analyzer::for_package(&dump, "com.missing").get_info("com.missing").is_none());
+}